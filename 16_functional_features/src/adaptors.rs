@@ -0,0 +1,142 @@
+/*
+  ** Iterator adaptors that the standard library doesn't have. They are lazy
+  ** like every other adaptor: nothing happens until something calls next,
+  ** and only as much of the underlying iterator is consumed as needed.
+  **
+  ** IteratorExt adds them as methods to every iterator, the same way the
+  ** methods of the Iterator trait are there once next is implemented:
+  **
+  **   use functional::adaptors::IteratorExt;
+  **
+  **   let v: Vec<_> = vec![1, 1, 2, 3, 3].into_iter().dedup_by(|a, b| a == b).collect();
+  **
+  ** size_hint is implemented by all of them and DoubleEndedIterator by the
+  ** ones that can go backwards without reading everything in front of them.
+*/
+
+pub mod chunk_by_key;
+pub mod counter;
+pub mod dedup_by;
+pub mod interleave;
+pub mod scan_while;
+pub mod windows;
+
+pub use chunk_by_key::ChunkByKey;
+pub use counter::Counter;
+pub use dedup_by::DedupBy;
+pub use interleave::Interleave;
+pub use scan_while::ScanWhile;
+pub use windows::Windows;
+
+pub trait IteratorExt: Iterator + Sized {
+  // groups runs of consecutive items with the same key,
+  // yielding the key along with the items of the run
+  fn chunk_by_key<K, F>(self, key: F) -> ChunkByKey<Self, K, F>
+    where K: PartialEq,
+          F: FnMut(&Self::Item) -> K
+  {
+    ChunkByKey::new(self, key)
+  }
+
+  // every run of size consecutive items, sliding one item at a time
+  // panics when size is 0, like slice::windows
+  fn windows(self, size: usize) -> Windows<Self>
+    where Self::Item: Clone
+  {
+    Windows::new(self, size)
+  }
+
+  // takes turns between both iterators, starting with this one,
+  // and goes on with whichever is left once the other runs out
+  fn interleave<J>(self, other: J) -> Interleave<Self, J::IntoIter>
+    where J: IntoIterator<Item = Self::Item>
+  {
+    Interleave::new(self, other.into_iter())
+  }
+
+  // skips the items that are the same as the last item yielded,
+  // same(kept, item) tells if item is the same as the kept one
+  fn dedup_by<F>(self, same: F) -> DedupBy<Self, F>
+    where F: FnMut(&Self::Item, &Self::Item) -> bool
+  {
+    DedupBy::new(self, same)
+  }
+
+  // yields the state after folding each item into it,
+  // stopping the first time f returns None
+  fn scan_while<S, F>(self, initial: S, f: F) -> ScanWhile<Self, S, F>
+    where S: Clone,
+          F: FnMut(&S, Self::Item) -> Option<S>
+  {
+    ScanWhile::new(self, initial, f)
+  }
+}
+
+impl<I: Iterator> IteratorExt for I {}
+
+// helpers for the property tests, which check every adaptor
+// against a naive version built on Vecs over many random inputs
+#[cfg(test)]
+pub(crate) mod check {
+  use crate::workout::rng::{Rng, XorShift};
+
+  // short vecs of small numbers, so runs of equal neighbours show up often
+  pub fn random_vecs(seed: u64) -> Vec<Vec<u8>> {
+    let mut rng = XorShift::new(seed);
+
+    (0..300)
+      .map(|_| {
+        let len = rng.below(16);
+        (0..len).map(|_| rng.below(4) as u8).collect()
+      })
+      .collect()
+  }
+
+  // exhausts the iterator checking every size_hint
+  // against how many items were actually left
+  pub fn size_hints<I: Iterator + Clone>(iter: I) {
+    let mut iter = iter;
+
+    loop {
+      let (lower, upper) = iter.size_hint();
+      let left = iter.clone().count();
+
+      assert!(lower <= left, "lower bound {} but {} left", lower, left);
+      if let Some(upper) = upper {
+        assert!(left <= upper, "upper bound {} but {} left", upper, left);
+      }
+
+      if iter.next().is_none() {
+        break;
+      }
+    }
+  }
+
+  // takes items from both ends in a random order, the
+  // result has to be what the iterator would give front to back
+  pub fn both_ends<I>(iter: I, seed: u64) -> Vec<I::Item>
+    where I: DoubleEndedIterator
+  {
+    let mut rng = XorShift::new(seed);
+    let mut iter = iter;
+    let (mut front, mut back) = (Vec::new(), Vec::new());
+
+    loop {
+      let item = if rng.below(2) == 0 {
+        iter.next().map(|item| front.push(item))
+      } else {
+        iter.next_back().map(|item| back.push(item))
+      };
+
+      if item.is_none() {
+        break;
+      }
+    }
+
+    // once one end ran out the other has to be empty too
+    assert!(iter.next().is_none() && iter.next_back().is_none());
+
+    front.extend(back.into_iter().rev());
+    front
+  }
+}
@@ -0,0 +1,141 @@
+/*
+  ** Groups runs of consecutive items that have the same key, a run only
+  ** ends when an item with another key shows up, so ChunkByKey always reads
+  ** one item past the run it yields and keeps it for the next one.
+  **
+  ** It doesn't go backwards: a run that is read from both ends at once
+  ** would have to be split between the front and the back.
+*/
+
+pub struct ChunkByKey<I: Iterator, K, F> {
+  iter: I,
+  key: F,
+  // the first item of the next run, with its key
+  next: Option<(K, I::Item)>,
+}
+
+impl<I, K, F> ChunkByKey<I, K, F>
+  where I: Iterator,
+        K: PartialEq,
+        F: FnMut(&I::Item) -> K
+{
+  pub(crate) fn new(iter: I, key: F) -> ChunkByKey<I, K, F> {
+    ChunkByKey { iter, key, next: None }
+  }
+
+  fn pull(&mut self) -> Option<(K, I::Item)> {
+    let item = self.iter.next()?;
+    Some(((self.key)(&item), item))
+  }
+}
+
+// derive(Clone) would miss that the kept item has to be Clone too
+impl<I, K, F> Clone for ChunkByKey<I, K, F>
+  where I: Iterator + Clone,
+        I::Item: Clone,
+        K: Clone,
+        F: Clone
+{
+  fn clone(&self) -> ChunkByKey<I, K, F> {
+    ChunkByKey {
+      iter: self.iter.clone(),
+      key: self.key.clone(),
+      next: self.next.clone(),
+    }
+  }
+}
+
+impl<I, K, F> Iterator for ChunkByKey<I, K, F>
+  where I: Iterator,
+        K: PartialEq,
+        F: FnMut(&I::Item) -> K
+{
+  type Item = (K, Vec<I::Item>);
+
+  fn next(&mut self) -> Option<(K, Vec<I::Item>)> {
+    let (key, first) = match self.next.take() {
+      Some(next) => next,
+      None => self.pull()?,
+    };
+
+    let mut chunk = vec![first];
+
+    while let Some((k, item)) = self.pull() {
+      if k != key {
+        self.next = Some((k, item));
+        break;
+      }
+      chunk.push(item);
+    }
+
+    Some((key, chunk))
+  }
+
+  // every item could have a key of its own, or they could all share one
+  fn size_hint(&self) -> (usize, Option<usize>) {
+    let kept = self.next.is_some() as usize;
+    let (lower, upper) = self.iter.size_hint();
+
+    (
+      lower.saturating_add(kept).min(1),
+      upper.and_then(|upper| upper.checked_add(kept)),
+    )
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::adaptors::check;
+  use crate::adaptors::IteratorExt;
+
+  // the naive version, grouping in a Vec of runs
+  fn naive(v: &[u8]) -> Vec<(bool, Vec<u8>)> {
+    let mut chunks: Vec<(bool, Vec<u8>)> = Vec::new();
+
+    for &x in v {
+      match chunks.last_mut() {
+        Some((key, chunk)) if *key == (x % 2 == 0) => chunk.push(x),
+        _ => chunks.push((x % 2 == 0, vec![x])),
+      }
+    }
+
+    chunks
+  }
+
+  #[test]
+  fn groups_runs() {
+    let chunks: Vec<_> = vec!["apple", "avocado", "banana", "apricot"]
+      .into_iter()
+      .chunk_by_key(|word| word.chars().next())
+      .collect();
+
+    assert_eq!(
+      vec![
+        (Some('a'), vec!["apple", "avocado"]),
+        (Some('b'), vec!["banana"]),
+        (Some('a'), vec!["apricot"]),
+      ],
+      chunks
+    );
+  }
+
+  #[test]
+  fn is_lazy() {
+    let read = std::cell::Cell::new(0);
+    let mut chunks = (0..).inspect(|_| read.set(read.get() + 1)).chunk_by_key(|n| n / 3);
+
+    assert_eq!(Some((0, vec![0, 1, 2])), chunks.next());
+    // 3 had to be read to know the run was over
+    assert_eq!(4, read.get());
+  }
+
+  #[test]
+  fn matches_the_naive_version() {
+    for v in check::random_vecs(1) {
+      let chunks: Vec<_> = v.iter().copied().chunk_by_key(|x| x % 2 == 0).collect();
+
+      assert_eq!(naive(&v), chunks);
+      check::size_hints(v.iter().chunk_by_key(|x| *x % 2 == 0));
+    }
+  }
+}
@@ -0,0 +1,108 @@
+/*
+  ** The Counter from the chapter, counting from 1 to 5, grown up a little:
+  ** it can count up to any number and, since it always knows how many
+  ** numbers are left, it can count down from the end and tell its length.
+*/
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Counter {
+  // the last number given from the front, 0 before the first one
+  count: u32,
+  // the next number to give from the back
+  end: u32,
+}
+
+impl Counter {
+  // counts from 1 to 5, like the one in the book
+  pub fn new() -> Counter {
+    Counter::up_to(5)
+  }
+
+  pub fn up_to(end: u32) -> Counter {
+    Counter { count: 0, end }
+  }
+}
+
+impl Default for Counter {
+  fn default() -> Counter {
+    Counter::new()
+  }
+}
+
+impl Iterator for Counter {
+  // associated types are seen in chapter 19
+  type Item = u32;
+
+  fn next(&mut self) -> Option<u32> {
+    if self.count < self.end {
+      self.count += 1;
+      Some(self.count)
+    } else {
+      None
+    }
+  }
+
+  fn size_hint(&self) -> (usize, Option<usize>) {
+    let left = (self.end - self.count) as usize;
+    (left, Some(left))
+  }
+}
+
+impl DoubleEndedIterator for Counter {
+  fn next_back(&mut self) -> Option<u32> {
+    if self.count < self.end {
+      self.end -= 1;
+      Some(self.end + 1)
+    } else {
+      None
+    }
+  }
+}
+
+impl ExactSizeIterator for Counter {}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::adaptors::check;
+
+  #[test]
+  fn calling_next_directly() {
+    let mut counter = Counter::new();
+
+    assert_eq!(counter.next(), Some(1));
+    assert_eq!(counter.next(), Some(2));
+    assert_eq!(counter.next(), Some(3));
+    assert_eq!(counter.next(), Some(4));
+    assert_eq!(counter.next(), Some(5));
+    assert_eq!(counter.next(), None);
+  }
+
+  #[test]
+  fn using_other_iterator_trait_methods() {
+    // the example from the book
+    let sum: u32 = Counter::new().zip(Counter::new().skip(1))
+      .map(|(a, b)| a * b)
+      .filter(|x| x % 3 == 0)
+      .sum();
+
+    assert_eq!(18, sum);
+  }
+
+  #[test]
+  fn counts_up_to_the_max() {
+    assert_eq!(Some(u32::MAX), Counter::up_to(u32::MAX).next_back());
+  }
+
+  #[test]
+  fn matches_a_range() {
+    for end in 0..40 {
+      let naive: Vec<u32> = (1..=end).collect();
+
+      assert_eq!(naive, Counter::up_to(end).collect::<Vec<_>>());
+      assert_eq!(naive, check::both_ends(Counter::up_to(end), u64::from(end)));
+      assert_eq!(naive.len(), Counter::up_to(end).len());
+      check::size_hints(Counter::up_to(end));
+    }
+  }
+}
@@ -0,0 +1,113 @@
+/*
+  ** Vec::dedup_by for any iterator: the items that are the same as the
+  ** last item kept are skipped. Only the first item of a run is kept and the
+  ** others are compared with it, not with their neighbour, so it gives the
+  ** same result as dedup_by even when `same` isn't transitive.
+  **
+  ** That's also why it doesn't go backwards: from the back the kept item
+  ** of a run isn't known until the whole run was read.
+*/
+
+pub struct DedupBy<I: Iterator, F> {
+  iter: I,
+  same: F,
+  // the first item that wasn't the same as the last one yielded
+  next: Option<I::Item>,
+}
+
+impl<I, F> DedupBy<I, F>
+  where I: Iterator,
+        F: FnMut(&I::Item, &I::Item) -> bool
+{
+  pub(crate) fn new(iter: I, same: F) -> DedupBy<I, F> {
+    DedupBy { iter, same, next: None }
+  }
+}
+
+// derive(Clone) would miss that the kept item has to be Clone too
+impl<I, F> Clone for DedupBy<I, F>
+  where I: Iterator + Clone,
+        I::Item: Clone,
+        F: Clone
+{
+  fn clone(&self) -> DedupBy<I, F> {
+    DedupBy {
+      iter: self.iter.clone(),
+      same: self.same.clone(),
+      next: self.next.clone(),
+    }
+  }
+}
+
+impl<I, F> Iterator for DedupBy<I, F>
+  where I: Iterator,
+        F: FnMut(&I::Item, &I::Item) -> bool
+{
+  type Item = I::Item;
+
+  fn next(&mut self) -> Option<I::Item> {
+    let kept = match self.next.take() {
+      Some(item) => item,
+      None => self.iter.next()?,
+    };
+
+    for item in &mut self.iter {
+      if !(self.same)(&kept, &item) {
+        self.next = Some(item);
+        break;
+      }
+    }
+
+    Some(kept)
+  }
+
+  // all the items could be the same, or all different
+  fn size_hint(&self) -> (usize, Option<usize>) {
+    let kept = self.next.is_some() as usize;
+    let (lower, upper) = self.iter.size_hint();
+
+    (
+      lower.saturating_add(kept).min(1),
+      upper.and_then(|upper| upper.checked_add(kept)),
+    )
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::adaptors::check;
+  use crate::adaptors::IteratorExt;
+
+  #[test]
+  fn skips_runs() {
+    let v: Vec<_> = "aaBbcA".chars()
+      .dedup_by(|a, b| a.eq_ignore_ascii_case(b))
+      .collect();
+
+    assert_eq!(vec!['a', 'B', 'c', 'A'], v);
+  }
+
+  #[test]
+  fn compares_with_the_kept_item() {
+    // every number is close to its neighbour, but not to the kept one
+    let v: Vec<_> = vec![1, 2, 3, 4, 5].into_iter()
+      .dedup_by(|kept: &i32, item| (kept - item).abs() <= 1)
+      .collect();
+
+    assert_eq!(vec![1, 3, 5], v);
+  }
+
+  #[test]
+  fn matches_vec_dedup_by() {
+    let same = |kept: &u8, item: &u8| kept.abs_diff(*item) <= 1;
+
+    for v in check::random_vecs(4) {
+      let mut naive = v.clone();
+      // Vec::dedup_by gets the item first and the kept one second
+      naive.dedup_by(|item, kept| same(kept, item));
+
+      assert_eq!(naive, v.iter().copied().dedup_by(same).collect::<Vec<_>>());
+      check::size_hints(v.iter().copied().dedup_by(same));
+    }
+  }
+}
@@ -0,0 +1,122 @@
+/*
+  ** Takes turns between two iterators, and once one of them runs out
+  ** goes on with whatever is left in the other.
+  **
+  ** To go backwards the lengths of both have to be known: the last item
+  ** comes from the longer one, or from the one whose turn isn't next when
+  ** they have the same length.
+*/
+
+#[derive(Clone)]
+pub struct Interleave<I, J> {
+  a: I,
+  b: J,
+  // whose turn it is at the front
+  a_next: bool,
+}
+
+impl<I, J> Interleave<I, J>
+  where I: Iterator,
+        J: Iterator<Item = I::Item>
+{
+  pub(crate) fn new(a: I, b: J) -> Interleave<I, J> {
+    Interleave { a, b, a_next: true }
+  }
+}
+
+impl<I, J> Iterator for Interleave<I, J>
+  where I: Iterator,
+        J: Iterator<Item = I::Item>
+{
+  type Item = I::Item;
+
+  fn next(&mut self) -> Option<I::Item> {
+    if self.a_next {
+      self.a_next = false;
+      self.a.next().or_else(|| self.b.next())
+    } else {
+      self.a_next = true;
+      self.b.next().or_else(|| self.a.next())
+    }
+  }
+
+  fn size_hint(&self) -> (usize, Option<usize>) {
+    let (a_lower, a_upper) = self.a.size_hint();
+    let (b_lower, b_upper) = self.b.size_hint();
+
+    let upper = match (a_upper, b_upper) {
+      (Some(a), Some(b)) => a.checked_add(b),
+      _ => None,
+    };
+
+    (a_lower.saturating_add(b_lower), upper)
+  }
+}
+
+impl<I, J> DoubleEndedIterator for Interleave<I, J>
+  where I: DoubleEndedIterator + ExactSizeIterator,
+        J: DoubleEndedIterator<Item = I::Item> + ExactSizeIterator
+{
+  fn next_back(&mut self) -> Option<I::Item> {
+    let (a, b) = (self.a.len(), self.b.len());
+
+    let from_a = if self.a_next { a > b } else { a >= b && a > 0 };
+
+    if from_a {
+      self.a.next_back()
+    } else {
+      self.b.next_back()
+    }
+  }
+}
+
+impl<I, J> ExactSizeIterator for Interleave<I, J>
+  where I: ExactSizeIterator,
+        J: ExactSizeIterator<Item = I::Item>
+{}
+
+#[cfg(test)]
+mod tests {
+  use crate::adaptors::check;
+  use crate::adaptors::IteratorExt;
+
+  fn naive(a: &[u8], b: &[u8]) -> Vec<u8> {
+    let mut v = Vec::new();
+
+    for i in 0..a.len().max(b.len()) {
+      v.extend(a.get(i));
+      v.extend(b.get(i));
+    }
+
+    v
+  }
+
+  #[test]
+  fn takes_turns() {
+    let v: Vec<_> = vec![1, 3, 5, 7].into_iter().interleave(vec![2, 4]).collect();
+
+    assert_eq!(vec![1, 2, 3, 4, 5, 7], v);
+  }
+
+  #[test]
+  fn is_lazy() {
+    let v: Vec<_> = (0..).step_by(2).interleave((1..).step_by(2)).take(5).collect();
+
+    assert_eq!(vec![0, 1, 2, 3, 4], v);
+  }
+
+  #[test]
+  fn matches_the_naive_version() {
+    let vecs = check::random_vecs(3);
+
+    for (seed, pair) in vecs.chunks(2).enumerate() {
+      let (a, b) = (&pair[0], &pair[1]);
+      let interleave = || a.iter().copied().interleave(b.iter().copied());
+
+      assert_eq!(naive(a, b), interleave().collect::<Vec<_>>());
+      assert_eq!(naive(a, b), check::both_ends(interleave(), seed as u64));
+      assert_eq!(a.len() + b.len(), interleave().len());
+      check::size_hints(interleave());
+    }
+  }
+}
@@ -0,0 +1,105 @@
+/*
+  ** A running fold: every item is folded into the state and the new state is
+  ** yielded, until the fold says to stop by returning None. Running totals
+  ** that stop before going over a budget are the usual example:
+  **
+  **   prices.scan_while(0, |total, price| Some(total + price).filter(|t| *t <= budget))
+  **
+  ** Unlike Iterator::scan the closure doesn't get to change the state in
+  ** place, it returns the next one, and the state is what comes out.
+  ** Every state depends on all the items before it, so it only goes forward.
+*/
+
+#[derive(Clone)]
+pub struct ScanWhile<I, S, F> {
+  iter: I,
+  // None once the fold stopped
+  state: Option<S>,
+  f: F,
+}
+
+impl<I, S, F> ScanWhile<I, S, F>
+  where I: Iterator,
+        S: Clone,
+        F: FnMut(&S, I::Item) -> Option<S>
+{
+  pub(crate) fn new(iter: I, initial: S, f: F) -> ScanWhile<I, S, F> {
+    ScanWhile { iter, state: Some(initial), f }
+  }
+}
+
+impl<I, S, F> Iterator for ScanWhile<I, S, F>
+  where I: Iterator,
+        S: Clone,
+        F: FnMut(&S, I::Item) -> Option<S>
+{
+  type Item = S;
+
+  fn next(&mut self) -> Option<S> {
+    let state = self.state.as_ref()?;
+    let item = self.iter.next()?;
+
+    // once it stopped it stays stopped, even if there are more items
+    self.state = (self.f)(state, item);
+    self.state.clone()
+  }
+
+  fn size_hint(&self) -> (usize, Option<usize>) {
+    match self.state {
+      Some(_) => (0, self.iter.size_hint().1),
+      None => (0, Some(0)),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::adaptors::check;
+  use crate::adaptors::IteratorExt;
+
+  fn naive(v: &[u8], budget: u32) -> Vec<u32> {
+    let mut totals = Vec::new();
+    let mut total = 0;
+
+    for &x in v {
+      total += u32::from(x);
+      if total > budget {
+        break;
+      }
+      totals.push(total);
+    }
+
+    totals
+  }
+
+  #[test]
+  fn running_totals_within_budget() {
+    let totals: Vec<_> = vec![3, 4, 2, 8, 1].into_iter()
+      .scan_while(0, |total, price| Some(total + price).filter(|t| *t <= 10))
+      .collect();
+
+    assert_eq!(vec![3, 7, 9], totals);
+  }
+
+  #[test]
+  fn stays_stopped() {
+    let mut scan = vec![1, 5, 1].into_iter()
+      .scan_while(0, |_, n| if n > 2 { None } else { Some(n) });
+
+    assert_eq!(Some(1), scan.next());
+    assert_eq!(None, scan.next());
+    assert_eq!(None, scan.next());
+    assert_eq!((0, Some(0)), scan.size_hint());
+  }
+
+  #[test]
+  fn matches_the_naive_version() {
+    for (budget, v) in check::random_vecs(5).into_iter().enumerate() {
+      let budget = budget as u32 % 20;
+      let scan = v.iter().scan_while(0, |total, x| Some(total + u32::from(*x)).filter(|t| *t <= budget));
+
+      assert_eq!(naive(&v, budget), scan.clone().collect::<Vec<_>>());
+      check::size_hints(scan);
+    }
+  }
+}
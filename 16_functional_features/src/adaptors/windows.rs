@@ -0,0 +1,148 @@
+/*
+  ** slice::windows for any iterator: every run of size consecutive items,
+  ** sliding one item at a time. The items are owned, so each window is a Vec
+  ** of clones and the last size - 1 items are kept around for the next one.
+  **
+  ** Going backwards works the same way from the other end. The items still
+  ** in the iterator sit between the ones kept at the front and at the back,
+  ** and when the iterator runs out each end takes what it needs from the
+  ** items the other end kept.
+*/
+
+use std::collections::VecDeque;
+
+pub struct Windows<I: Iterator> {
+  iter: I,
+  size: usize,
+  front: VecDeque<I::Item>,
+  back: VecDeque<I::Item>,
+}
+
+impl<I> Windows<I>
+  where I: Iterator,
+        I::Item: Clone
+{
+  pub(crate) fn new(iter: I, size: usize) -> Windows<I> {
+    assert!(size > 0, "windows need at least one item");
+
+    Windows {
+      iter,
+      size,
+      front: VecDeque::with_capacity(size),
+      back: VecDeque::new(),
+    }
+  }
+}
+
+// derive(Clone) would miss that the kept items have to be Clone too
+impl<I> Clone for Windows<I>
+  where I: Iterator + Clone,
+        I::Item: Clone
+{
+  fn clone(&self) -> Windows<I> {
+    Windows {
+      iter: self.iter.clone(),
+      size: self.size,
+      front: self.front.clone(),
+      back: self.back.clone(),
+    }
+  }
+}
+
+impl<I> Iterator for Windows<I>
+  where I: Iterator,
+        I::Item: Clone
+{
+  type Item = Vec<I::Item>;
+
+  fn next(&mut self) -> Option<Vec<I::Item>> {
+    while self.front.len() < self.size {
+      let item = match self.iter.next() {
+        Some(item) => item,
+        None => self.back.pop_front()?,
+      };
+      self.front.push_back(item);
+    }
+
+    let window = self.front.iter().cloned().collect();
+    self.front.pop_front();
+    Some(window)
+  }
+
+  // the items left make len - size + 1 windows, when there are enough of them
+  fn size_hint(&self) -> (usize, Option<usize>) {
+    let kept = self.front.len() + self.back.len();
+    let (lower, upper) = self.iter.size_hint();
+    let windows = |items: usize| items.saturating_sub(self.size - 1);
+
+    (
+      windows(lower.saturating_add(kept)),
+      upper.and_then(|upper| upper.checked_add(kept)).map(windows),
+    )
+  }
+}
+
+impl<I> DoubleEndedIterator for Windows<I>
+  where I: DoubleEndedIterator,
+        I::Item: Clone
+{
+  fn next_back(&mut self) -> Option<Vec<I::Item>> {
+    while self.back.len() < self.size {
+      let item = match self.iter.next_back() {
+        Some(item) => item,
+        None => self.front.pop_back()?,
+      };
+      self.back.push_front(item);
+    }
+
+    let window = self.back.iter().cloned().collect();
+    self.back.pop_back();
+    Some(window)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::adaptors::check;
+  use crate::adaptors::IteratorExt;
+
+  #[test]
+  fn slides_one_item_at_a_time() {
+    let windows: Vec<_> = "abcd".chars().windows(2).collect();
+
+    assert_eq!(vec![vec!['a', 'b'], vec!['b', 'c'], vec!['c', 'd']], windows);
+  }
+
+  #[test]
+  fn too_few_items() {
+    assert_eq!(None, vec![1, 2].into_iter().windows(3).next());
+    assert_eq!(None, vec![1, 2].into_iter().windows(3).next_back());
+  }
+
+  #[test]
+  fn is_lazy() {
+    let mut windows = (0..).windows(3);
+
+    assert_eq!(Some(vec![0, 1, 2]), windows.next());
+    assert_eq!(Some(vec![1, 2, 3]), windows.next());
+  }
+
+  #[test]
+  #[should_panic]
+  fn zero_size_panics() {
+    (0..3).windows(0);
+  }
+
+  #[test]
+  fn matches_slice_windows() {
+    for (seed, v) in check::random_vecs(2).into_iter().enumerate() {
+      for size in 1..5 {
+        let naive: Vec<Vec<u8>> = v.windows(size).map(|w| w.to_vec()).collect();
+
+        assert_eq!(naive, v.iter().copied().windows(size).collect::<Vec<_>>());
+        assert_eq!(naive, check::both_ends(v.iter().copied().windows(size), seed as u64));
+        check::size_hints(v.iter().windows(size));
+      }
+    }
+  }
+}
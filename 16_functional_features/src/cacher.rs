@@ -0,0 +1,422 @@
+// standard crates
+use std::hash::Hash;
+use std::io;
+use std::time::{Duration, Instant, SystemTime};
+
+// Traits
+use std::clone::Clone;
+
+pub mod asynchronous;
+pub mod clock;
+pub mod fallible;
+pub mod lru;
+#[doc(hidden)]
+pub mod memo;
+pub mod persist;
+pub mod sync;
+
+use clock::{Clock, SystemClock};
+use lru::Lru;
+use persist::Backing;
+
+// how well the cache is doing, a hit is a value that
+// didn't have to be calculated again
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct CacheStats {
+  pub hits: u64,
+  pub misses: u64,
+  pub evictions: u64,
+  // misses caused by a value that was cached but had expired
+  pub expirations: u64,
+}
+
+// a cached value and when it stops being valid, None means never
+struct Entry<R> {
+  value: R,
+  expires_at: Option<Instant>,
+}
+
+impl<R> Entry<R> {
+  fn is_expired(&self, now: Instant) -> bool {
+    match self.expires_at {
+      Some(at) => now >= at,
+      None => false,
+    }
+  }
+}
+
+// a time to live too big to be added to now just never expires
+fn expiration(now: Instant, ttl: Option<Duration>) -> Option<Instant> {
+  ttl.and_then(|ttl| now.checked_add(ttl))
+}
+
+// the same moment as at on the wall clock, for saving it to a file
+fn wall_clock(at: Option<Instant>, now: Instant, wall_now: SystemTime) -> Option<SystemTime> {
+  at.and_then(|at| wall_now.checked_add(at.saturating_duration_since(now)))
+}
+
+// R defaults to u32 since that's what the workout plan
+// calculation returns, so Cacher<T, A> still means the same thing
+pub struct Cacher<T, A, R = u32, C = SystemClock>
+  where T: Fn(A) -> R,
+        A: Eq + Hash + Clone,
+        C: Clock
+{
+  calculation: T,
+  value: Lru<A, Entry<R>>,
+  stats: CacheStats,
+  // how long values live when they don't get a ttl of their own
+  ttl: Option<Duration>,
+  clock: C,
+  // the file values are saved to, see Cacher::persist
+  log: Option<Box<dyn Backing<A, R> + Send>>,
+  // why the cacher stopped saving to its file
+  log_error: Option<io::Error>,
+}
+
+impl<T, A, R> Cacher<T, A, R>
+  where T: Fn(A) -> R,
+        A: Eq + Hash + Clone
+{
+  // keeps every value it ever calculated
+  pub fn new(calculation: T) -> Cacher<T, A, R> {
+    Cacher {
+      calculation,
+      value: Lru::unbounded(),
+      stats: CacheStats::default(),
+      ttl: None,
+      clock: SystemClock,
+      log: None,
+      log_error: None,
+    }
+  }
+
+  // keeps at most capacity values, forgetting the
+  // least recently used one when it needs room for another
+  // panics when capacity is 0, there would be no room for any value
+  pub fn with_capacity(capacity: usize, calculation: T) -> Cacher<T, A, R> {
+    Cacher {
+      calculation,
+      value: Lru::with_capacity(capacity),
+      stats: CacheStats::default(),
+      ttl: None,
+      clock: SystemClock,
+      log: None,
+      log_error: None,
+    }
+  }
+}
+
+impl<T, A, R, C> Cacher<T, A, R, C>
+  where T: Fn(A) -> R,
+        A: Eq + Hash + Clone,
+        C: Clock
+{
+  // values calculated from now on expire after ttl
+  pub fn with_ttl(mut self, ttl: Duration) -> Cacher<T, A, R, C> {
+    self.ttl = Some(ttl);
+    self
+  }
+
+  // reads the time from another clock, e.g. a ManualClock in tests
+  pub fn with_clock<D: Clock>(self, clock: D) -> Cacher<T, A, R, D> {
+    Cacher {
+      calculation: self.calculation,
+      value: self.value,
+      stats: self.stats,
+      ttl: self.ttl,
+      clock,
+      log: self.log,
+      log_error: self.log_error,
+    }
+  }
+
+  // borrows the cached value instead of cloning it, which works for
+  // values that can't be cloned or are expensive to clone
+  // to share them after the borrow ends, have the calculation return an Rc<R>
+  pub fn value_ref(&mut self, arg: A) -> &R {
+    let ttl = self.ttl;
+    self.lookup(arg, ttl)
+  }
+
+  // like value_ref but if the value has to be calculated it
+  // lives for ttl instead of the cacher's time to live
+  // a value that is still cached keeps the expiration it had
+  pub fn value_ref_with_ttl(&mut self, arg: A, ttl: Duration) -> &R {
+    self.lookup(arg, Some(ttl))
+  }
+
+  // the cached value for arg, if there is one that didn't expire
+  // this never calculates anything, it's for callers that calculate the value
+  // themselves and then store it, like the Memo behind #[memoize], that
+  // can't hold on to its cacher while the memoized function calls itself
+  pub(crate) fn cached(&mut self, arg: &A) -> Option<&R> {
+    if self.is_fresh(arg) {
+      self.value.peek(arg).map(|entry| &entry.value)
+    } else {
+      None
+    }
+  }
+
+  // caches a value that was calculated outside of the cacher,
+  // it's counted as a miss just like if the cacher had calculated it
+  pub(crate) fn store(&mut self, arg: A, value: R) -> &R {
+    let ttl = self.ttl;
+    self.put(arg.clone(), value, ttl);
+    &self.value.peek(&arg).unwrap().value
+  }
+
+  // expired values are only found out, and calculated again,
+  // when they are asked for
+  fn lookup(&mut self, arg: A, ttl: Option<Duration>) -> &R {
+    if !self.is_fresh(&arg) {
+      let value = (self.calculation)(arg.clone());
+      self.put(arg.clone(), value, ttl);
+    }
+
+    // the value was either there already or was just inserted
+    &self.value.get(&arg).unwrap().value
+  }
+
+  // a hit when the value is cached and didn't expire yet
+  fn is_fresh(&mut self, arg: &A) -> bool {
+    let now = self.clock.now();
+
+    match self.value.get(arg) {
+      Some(entry) if !entry.is_expired(now) => {
+        self.stats.hits += 1;
+        true
+      },
+      Some(_) => {
+        self.stats.expirations += 1;
+        false
+      },
+      None => false,
+    }
+  }
+
+  fn put(&mut self, arg: A, value: R, ttl: Option<Duration>) {
+    self.stats.misses += 1;
+
+    let entry = Entry {
+      value,
+      expires_at: expiration(self.clock.now(), ttl),
+    };
+
+    if self.value.insert(arg.clone(), entry).is_some() {
+      self.stats.evictions += 1;
+    }
+    self.save(&arg);
+  }
+
+  pub fn stats(&self) -> CacheStats {
+    self.stats
+  }
+
+  // rewrites the log with only the values that are still cached
+  // does nothing for a cacher that wasn't opened from a file
+  pub fn compact(&mut self) -> io::Result<()> {
+    let log = match &mut self.log {
+      Some(log) => log,
+      None => return Ok(()),
+    };
+
+    let now = self.clock.now();
+    let wall_now = SystemTime::now();
+    let value = &self.value;
+    // from the least to the most recently used, so
+    // opening the log again gives back the same order
+    let entries: Vec<_> = value.keys().into_iter().rev()
+      .map(|arg| (arg, value.peek(arg).unwrap()))
+      .filter(|(_, entry)| !entry.is_expired(now))
+      .map(|(arg, entry)| (arg, &entry.value, wall_clock(entry.expires_at, now, wall_now)))
+      .collect();
+
+    log.rewrite(&entries)
+  }
+
+  // appends a value that was just calculated to the log
+  fn save(&mut self, arg: &A) {
+    let log = match &mut self.log {
+      Some(log) => log,
+      None => return,
+    };
+
+    let entry = self.value.peek(arg).unwrap();
+    let expires_at = wall_clock(entry.expires_at, self.clock.now(), SystemTime::now());
+
+    let mut saved = log.append(arg, &entry.value, expires_at);
+    if saved.is_ok() && persist::needs_compaction(log.records(), self.value.len()) {
+      saved = self.compact();
+    }
+
+    // the values are still cached in memory, the cacher just stops saving them
+    if let Err(e) = saved {
+      self.log = None;
+      self.log_error = Some(e);
+    }
+  }
+
+  // the error that made the cacher stop saving values to its log,
+  // None while it's still saving them or if it never had a log
+  pub fn log_error(&self) -> Option<&io::Error> {
+    self.log_error.as_ref()
+  }
+
+  pub fn len(&self) -> usize {
+    self.value.len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.value.is_empty()
+  }
+}
+
+impl<T, A, R, C> Cacher<T, A, R, C>
+  where T: Fn(A) -> R,
+        A: Eq + Hash + Clone,
+        R: Clone,
+        C: Clock
+{
+  pub fn value(&mut self, arg: A) -> R {
+    self.value_ref(arg).clone()
+  }
+
+  pub fn value_with_ttl(&mut self, arg: A, ttl: Duration) -> R {
+    self.value_ref_with_ttl(arg, ttl).clone()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::cell::Cell;
+  use std::rc::Rc;
+
+  use clock::ManualClock;
+
+  #[test]
+  fn calculates_each_value_once() {
+    let calls = Cell::new(0);
+    let mut c = Cacher::new(|num: u32| {
+      calls.set(calls.get() + 1);
+      num * 2
+    });
+
+    assert_eq!(4, c.value(2));
+    assert_eq!(4, c.value(2));
+    assert_eq!(6, c.value(3));
+
+    assert_eq!(2, calls.get());
+    assert_eq!(CacheStats { hits: 1, misses: 2, evictions: 0, expirations: 0 }, c.stats());
+  }
+
+  #[test]
+  fn bounded_cacher_evicts() {
+    let calls = Cell::new(0);
+    let mut c = Cacher::with_capacity(2, |num: u32| {
+      calls.set(calls.get() + 1);
+      num
+    });
+
+    c.value(1);
+    c.value(2);
+    c.value(1);
+    // 2 is the least recently used, so it goes
+    c.value(3);
+    c.value(1);
+    c.value(2);
+
+    assert_eq!(2, c.len());
+    assert_eq!(4, calls.get());
+    assert_eq!(CacheStats { hits: 2, misses: 4, evictions: 2, expirations: 0 }, c.stats());
+  }
+
+  #[test]
+  #[should_panic]
+  fn zero_capacity_panics() {
+    Cacher::with_capacity(0, |num: u32| num);
+  }
+
+  #[test]
+  fn any_clone_return_type() {
+    let mut c = Cacher::new(|name: &str| -> Result<String, String> {
+      match name {
+        "" => Err(String::from("empty name")),
+        _ => Ok(format!("hello {}", name)),
+      }
+    });
+
+    assert_eq!(Ok(String::from("hello bob")), c.value("bob"));
+    assert_eq!(Err(String::from("empty name")), c.value(""));
+  }
+
+  #[test]
+  fn value_ref_does_not_need_clone() {
+    struct Plan {
+      reps: u32,
+    }
+
+    let mut c = Cacher::new(|intensity: u32| Plan { reps: intensity * 2 });
+
+    assert_eq!(20, c.value_ref(10).reps);
+    assert_eq!(20, c.value_ref(10).reps);
+    assert_eq!(1, c.stats().hits);
+  }
+
+  #[test]
+  fn rc_values_are_shared() {
+    let mut c = Cacher::new(|n: usize| Rc::new(vec![0u8; n]));
+
+    let a = c.value(3);
+    let b = c.value(3);
+
+    assert!(Rc::ptr_eq(&a, &b));
+  }
+
+  #[test]
+  fn expired_values_are_calculated_again() {
+    let clock = ManualClock::new();
+    let calls = Cell::new(0);
+    let mut c = Cacher::new(|num: u32| {
+      calls.set(calls.get() + 1);
+      num
+    })
+      .with_ttl(Duration::from_secs(60))
+      .with_clock(clock.clone());
+
+    c.value(1);
+    clock.advance(Duration::from_secs(59));
+    c.value(1);
+    assert_eq!(1, calls.get());
+
+    clock.advance(Duration::from_secs(1));
+    c.value(1);
+    assert_eq!(2, calls.get());
+    assert_eq!(CacheStats { hits: 1, misses: 2, evictions: 0, expirations: 1 }, c.stats());
+  }
+
+  #[test]
+  fn entries_can_have_their_own_ttl() {
+    let clock = ManualClock::new();
+    let calls = Cell::new(0);
+    let mut c = Cacher::new(|num: u32| {
+      calls.set(calls.get() + 1);
+      num
+    }).with_clock(clock.clone());
+
+    c.value(1);
+    c.value_with_ttl(2, Duration::from_secs(5));
+
+    clock.advance(Duration::from_secs(3600));
+    c.value(1);
+    assert_eq!(2, calls.get());
+
+    c.value(2);
+    assert_eq!(3, calls.get());
+    // calculated again by value, so it doesn't expire anymore
+    clock.advance(Duration::from_secs(3600));
+    c.value(2);
+    assert_eq!(3, calls.get());
+  }
+}
@@ -0,0 +1,369 @@
+/*
+  ** A Cacher for calculations that are async, the ones that return a Future.
+  **
+  ** The values are kept in the same Lru as the Cacher's, so capacity, time to
+  ** live and stats work the same way. Like the SyncCacher, only the first
+  ** request for a key runs the calculation: it leaves a flight in the pending
+  ** map and the requests that come while it's running wait on that flight
+  ** instead of calculating the same value again.
+  **
+  ** A future can be dropped before it finishes. If that happens to the one
+  ** running the calculation, the flight is taken out and whoever was waiting
+  ** on it tries again, one of them becoming the one that calculates.
+*/
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::hash::Hash;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+use std::time::Duration;
+
+use super::clock::{Clock, SystemClock};
+use super::lru::Lru;
+use super::{expiration, CacheStats, Entry};
+
+enum FlightState<R> {
+  // the wakers of the futures waiting on the value
+  Running(Vec<Waker>),
+  Done(R),
+  // the calculation was dropped, whoever was waiting has to try again
+  Failed,
+}
+
+struct Flight<R> {
+  state: Mutex<FlightState<R>>,
+}
+
+impl<R: Clone> Flight<R> {
+  fn new() -> Flight<R> {
+    Flight {
+      state: Mutex::new(FlightState::Running(Vec::new())),
+    }
+  }
+
+  fn finish(&self, state: FlightState<R>) {
+    let previous = std::mem::replace(&mut *self.state.lock().unwrap(), state);
+
+    if let FlightState::Running(wakers) = previous {
+      for waker in wakers {
+        waker.wake();
+      }
+    }
+  }
+
+  // resolves once the calculation finished, to None if it was dropped
+  fn wait(&self) -> Wait<'_, R> {
+    Wait { flight: self }
+  }
+}
+
+struct Wait<'a, R> {
+  flight: &'a Flight<R>,
+}
+
+impl<'a, R: Clone> Future for Wait<'a, R> {
+  type Output = Option<R>;
+
+  fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<R>> {
+    match &mut *self.flight.state.lock().unwrap() {
+      FlightState::Running(wakers) => {
+        if !wakers.iter().any(|w| w.will_wake(cx.waker())) {
+          wakers.push(cx.waker().clone());
+        }
+        Poll::Pending
+      },
+      FlightState::Done(v) => Poll::Ready(Some(v.clone())),
+      FlightState::Failed => Poll::Ready(None),
+    }
+  }
+}
+
+struct State<A, R> {
+  value: Lru<A, Entry<R>>,
+  pending: HashMap<A, Arc<Flight<R>>>,
+  stats: CacheStats,
+}
+
+// what a request does after looking at the state
+enum Role<R> {
+  Cached(R),
+  Wait(Arc<Flight<R>>),
+  Calculate(Arc<Flight<R>>),
+}
+
+pub struct AsyncCacher<T, A, R, C = SystemClock>
+  where A: Eq + Hash + Clone,
+        C: Clock
+{
+  calculation: T,
+  state: Mutex<State<A, R>>,
+  ttl: Option<Duration>,
+  clock: C,
+}
+
+impl<T, F, A, R> AsyncCacher<T, A, R>
+  where T: Fn(A) -> F,
+        F: Future<Output = R>,
+        A: Eq + Hash + Clone,
+        R: Clone
+{
+  pub fn new(calculation: T) -> AsyncCacher<T, A, R> {
+    AsyncCacher::with_lru(Lru::unbounded(), calculation)
+  }
+
+  // keeps at most capacity values, forgetting the
+  // least recently used one when it needs room for another
+  // panics when capacity is 0, like Cacher::with_capacity
+  pub fn with_capacity(capacity: usize, calculation: T) -> AsyncCacher<T, A, R> {
+    AsyncCacher::with_lru(Lru::with_capacity(capacity), calculation)
+  }
+
+  fn with_lru(value: Lru<A, Entry<R>>, calculation: T) -> AsyncCacher<T, A, R> {
+    AsyncCacher {
+      calculation,
+      state: Mutex::new(State {
+        value,
+        pending: HashMap::new(),
+        stats: CacheStats::default(),
+      }),
+      ttl: None,
+      clock: SystemClock,
+    }
+  }
+}
+
+impl<T, F, A, R, C> AsyncCacher<T, A, R, C>
+  where T: Fn(A) -> F,
+        F: Future<Output = R>,
+        A: Eq + Hash + Clone,
+        R: Clone,
+        C: Clock
+{
+  // values calculated from now on expire after ttl
+  pub fn with_ttl(mut self, ttl: Duration) -> AsyncCacher<T, A, R, C> {
+    self.ttl = Some(ttl);
+    self
+  }
+
+  // reads the time from another clock, e.g. a ManualClock in tests
+  pub fn with_clock<D: Clock>(self, clock: D) -> AsyncCacher<T, A, R, D> {
+    AsyncCacher {
+      calculation: self.calculation,
+      state: self.state,
+      ttl: self.ttl,
+      clock,
+    }
+  }
+
+  // takes &self, so many futures can be waiting on it at the same time
+  pub async fn value(&self, arg: A) -> R {
+    loop {
+      match self.role(&arg) {
+        Role::Cached(v) => return v,
+        Role::Wait(flight) => {
+          if let Some(v) = flight.wait().await {
+            self.state.lock().unwrap().stats.hits += 1;
+            return v;
+          }
+        },
+        Role::Calculate(flight) => return self.calculate(arg, flight).await,
+      }
+    }
+  }
+
+  pub fn stats(&self) -> CacheStats {
+    self.state.lock().unwrap().stats
+  }
+
+  pub fn len(&self) -> usize {
+    self.state.lock().unwrap().value.len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.state.lock().unwrap().value.is_empty()
+  }
+
+  // the lock is only held in here, never across an await
+  fn role(&self, arg: &A) -> Role<R> {
+    let now = self.clock.now();
+    let mut state = self.state.lock().unwrap();
+
+    let expired = match state.value.get(arg) {
+      Some(entry) if !entry.is_expired(now) => {
+        let v = entry.value.clone();
+        state.stats.hits += 1;
+        return Role::Cached(v);
+      },
+      Some(_) => true,
+      None => false,
+    };
+
+    if let Some(flight) = state.pending.get(arg) {
+      return Role::Wait(Arc::clone(flight));
+    }
+
+    if expired {
+      state.stats.expirations += 1;
+    }
+    state.stats.misses += 1;
+
+    let flight = Arc::new(Flight::new());
+    state.pending.insert(arg.clone(), Arc::clone(&flight));
+    Role::Calculate(flight)
+  }
+
+  async fn calculate(&self, arg: A, flight: Arc<Flight<R>>) -> R {
+    let mut guard = FlightGuard {
+      state: &self.state,
+      arg: &arg,
+      flight: &flight,
+      finished: false,
+    };
+
+    let v = (self.calculation)(arg.clone()).await;
+
+    {
+      let mut state = self.state.lock().unwrap();
+      state.pending.remove(&arg);
+
+      let entry = Entry {
+        value: v.clone(),
+        expires_at: expiration(self.clock.now(), self.ttl),
+      };
+      if state.value.insert(arg.clone(), entry).is_some() {
+        state.stats.evictions += 1;
+      }
+    }
+
+    flight.finish(FlightState::Done(v.clone()));
+    guard.finished = true;
+
+    v
+  }
+}
+
+// if the calculating future is dropped, or the calculation panics, this takes
+// the flight out of the pending map and wakes up whoever was waiting on it
+struct FlightGuard<'a, A, R>
+  where A: Eq + Hash,
+        R: Clone
+{
+  state: &'a Mutex<State<A, R>>,
+  arg: &'a A,
+  flight: &'a Arc<Flight<R>>,
+  finished: bool,
+}
+
+impl<'a, A, R> Drop for FlightGuard<'a, A, R>
+  where A: Eq + Hash,
+        R: Clone
+{
+  fn drop(&mut self) {
+    if self.finished {
+      return;
+    }
+
+    if let Ok(mut state) = self.state.lock() {
+      state.pending.remove(self.arg);
+    }
+    self.flight.finish(FlightState::Failed);
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::cell::Cell;
+  use std::task::Wake;
+
+  use crate::cacher::clock::ManualClock;
+  use crate::executor::{block_on, join_all, yield_now};
+
+  struct NoopWaker;
+
+  impl Wake for NoopWaker {
+    fn wake(self: Arc<Self>) {}
+  }
+
+  fn poll_once<F: Future>(future: Pin<&mut F>) -> Poll<F::Output> {
+    let waker = Waker::from(Arc::new(NoopWaker));
+    future.poll(&mut Context::from_waker(&waker))
+  }
+
+  #[test]
+  fn calculates_each_value_once() {
+    let calls = Cell::new(0);
+    let c = AsyncCacher::new(|num: u32| {
+      calls.set(calls.get() + 1);
+      async move { num * 2 }
+    });
+
+    assert_eq!(4, block_on(c.value(2)));
+    assert_eq!(4, block_on(c.value(2)));
+
+    assert_eq!(1, calls.get());
+    assert_eq!(CacheStats { hits: 1, misses: 1, ..CacheStats::default() }, c.stats());
+  }
+
+  #[test]
+  fn concurrent_requests_share_one_calculation() {
+    let calls = Cell::new(0);
+    let c = AsyncCacher::new(|num: u32| {
+      calls.set(calls.get() + 1);
+      async move {
+        // the other requests come in while this one is running
+        yield_now().await;
+        num * 2
+      }
+    });
+
+    let values = block_on(join_all(vec![c.value(21), c.value(21), c.value(21)]));
+
+    assert_eq!(vec![42, 42, 42], values);
+    assert_eq!(1, calls.get());
+    assert_eq!(CacheStats { hits: 2, misses: 1, ..CacheStats::default() }, c.stats());
+  }
+
+  #[test]
+  fn a_dropped_calculation_is_started_again() {
+    let calls = Cell::new(0);
+    let c = AsyncCacher::new(|num: u32| {
+      calls.set(calls.get() + 1);
+      async move {
+        yield_now().await;
+        num
+      }
+    });
+
+    let mut first = Box::pin(c.value(1));
+    assert!(poll_once(first.as_mut()).is_pending());
+
+    let mut waiting = Box::pin(c.value(1));
+    assert!(poll_once(waiting.as_mut()).is_pending());
+    drop(first);
+
+    // the waiting request takes over the calculation
+    assert_eq!(1, block_on(waiting));
+    assert_eq!(2, calls.get());
+    assert!(c.state.lock().unwrap().pending.is_empty());
+  }
+
+  #[test]
+  fn evicts_and_expires_like_the_cacher() {
+    let clock = ManualClock::new();
+    let c = AsyncCacher::with_capacity(1, |num: u32| async move { num })
+      .with_ttl(Duration::from_secs(60))
+      .with_clock(clock.clone());
+
+    block_on(c.value(1));
+    block_on(c.value(1));
+    clock.advance(Duration::from_secs(60));
+    block_on(c.value(1));
+    block_on(c.value(2));
+
+    assert_eq!(1, c.len());
+    assert_eq!(CacheStats { hits: 1, misses: 3, evictions: 1, expirations: 1 }, c.stats());
+  }
+}
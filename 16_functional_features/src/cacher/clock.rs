@@ -0,0 +1,78 @@
+/*
+  ** Where the cachers get the current time from. Entries with a time to live
+  ** compare their expiration against it, and retries wait through it,
+  ** so code that depends on time passing can be tested with a ManualClock
+  ** instead of sleeping.
+*/
+
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+pub trait Clock {
+  fn now(&self) -> Instant;
+
+  // waits until the given time has passed
+  fn sleep(&self, duration: Duration) {
+    thread::sleep(duration);
+  }
+}
+
+// the real time
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+  fn now(&self) -> Instant {
+    Instant::now()
+  }
+}
+
+// a clock picked while the program runs, like
+// the one given to #[memoize(clock = ...)]
+impl Clock for Box<dyn Clock + Send> {
+  fn now(&self) -> Instant {
+    (**self).now()
+  }
+
+  fn sleep(&self, duration: Duration) {
+    (**self).sleep(duration);
+  }
+}
+
+// a clock that only moves when it's told to
+// clones share the same time, so a test can keep one and
+// hand the other to a cacher
+#[derive(Debug, Clone)]
+pub struct ManualClock {
+  now: Arc<Mutex<Instant>>,
+}
+
+impl ManualClock {
+  pub fn new() -> ManualClock {
+    ManualClock {
+      now: Arc::new(Mutex::new(Instant::now())),
+    }
+  }
+
+  pub fn advance(&self, by: Duration) {
+    *self.now.lock().unwrap() += by;
+  }
+}
+
+impl Default for ManualClock {
+  fn default() -> ManualClock {
+    ManualClock::new()
+  }
+}
+
+impl Clock for ManualClock {
+  fn now(&self) -> Instant {
+    *self.now.lock().unwrap()
+  }
+
+  // nothing to wait for, the time just moves forward
+  fn sleep(&self, duration: Duration) {
+    self.advance(duration);
+  }
+}
@@ -0,0 +1,284 @@
+/*
+  ** A Cacher for calculations that can fail, the ones that return a Result.
+  **
+  ** Successful values are cached just like in the Cacher, what happens with
+  ** the errors is up to the ErrorPolicy: they can be forgotten so the next
+  ** call tries again, remembered for a while so a failing calculation isn't
+  ** hammered (a negative cache), or retried right away waiting a bit longer
+  ** before every attempt (exponential backoff).
+*/
+
+use std::hash::Hash;
+use std::time::Duration;
+
+use super::clock::{Clock, SystemClock};
+use super::lru::Lru;
+use super::{expiration, CacheStats, Entry};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ErrorPolicy {
+  // errors are returned but not cached, the next call calculates again
+  NoCache,
+  // errors are cached for this long, then calculated again
+  CacheFor(Duration),
+  // the calculation runs again up to `retries` times, waiting `backoff`
+  // before the first retry and twice as long before every other one
+  // the error of the last attempt is returned and not cached
+  Retry { retries: u32, backoff: Duration },
+}
+
+pub struct TryCacher<T, A, R, E, C = SystemClock>
+  where T: Fn(A) -> Result<R, E>,
+        A: Eq + Hash + Clone,
+        C: Clock
+{
+  calculation: T,
+  value: Lru<A, Entry<Result<R, E>>>,
+  policy: ErrorPolicy,
+  stats: CacheStats,
+  // how long successful values live
+  ttl: Option<Duration>,
+  clock: C,
+}
+
+impl<T, A, R, E> TryCacher<T, A, R, E>
+  where T: Fn(A) -> Result<R, E>,
+        A: Eq + Hash + Clone
+{
+  pub fn new(policy: ErrorPolicy, calculation: T) -> TryCacher<T, A, R, E> {
+    TryCacher {
+      calculation,
+      value: Lru::unbounded(),
+      policy,
+      stats: CacheStats::default(),
+      ttl: None,
+      clock: SystemClock,
+    }
+  }
+
+  // cached errors take room like any other value
+  // panics when capacity is 0, like Cacher::with_capacity
+  pub fn with_capacity(capacity: usize, policy: ErrorPolicy, calculation: T) -> TryCacher<T, A, R, E> {
+    TryCacher {
+      calculation,
+      value: Lru::with_capacity(capacity),
+      policy,
+      stats: CacheStats::default(),
+      ttl: None,
+      clock: SystemClock,
+    }
+  }
+}
+
+impl<T, A, R, E, C> TryCacher<T, A, R, E, C>
+  where T: Fn(A) -> Result<R, E>,
+        A: Eq + Hash + Clone,
+        C: Clock
+{
+  // successful values calculated from now on expire after ttl
+  pub fn with_ttl(mut self, ttl: Duration) -> TryCacher<T, A, R, E, C> {
+    self.ttl = Some(ttl);
+    self
+  }
+
+  // reads the time, and waits between retries, with another clock
+  pub fn with_clock<D: Clock>(self, clock: D) -> TryCacher<T, A, R, E, D> {
+    TryCacher {
+      calculation: self.calculation,
+      value: self.value,
+      policy: self.policy,
+      stats: self.stats,
+      ttl: self.ttl,
+      clock,
+    }
+  }
+
+  pub fn policy(&self) -> ErrorPolicy {
+    self.policy
+  }
+
+  pub fn stats(&self) -> CacheStats {
+    self.stats
+  }
+
+  pub fn len(&self) -> usize {
+    self.value.len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.value.is_empty()
+  }
+
+  // runs the calculation as many times as the policy allows
+  fn calculate(&self, arg: &A) -> Result<R, E> {
+    let (retries, mut backoff) = match self.policy {
+      ErrorPolicy::Retry { retries, backoff } => (retries, backoff),
+      _ => (0, Duration::from_secs(0)),
+    };
+
+    let mut result = (self.calculation)(arg.clone());
+
+    for _ in 0..retries {
+      if result.is_ok() {
+        break;
+      }
+
+      self.clock.sleep(backoff);
+      backoff = backoff.checked_mul(2).unwrap_or(backoff);
+      result = (self.calculation)(arg.clone());
+    }
+
+    result
+  }
+}
+
+impl<T, A, R, E, C> TryCacher<T, A, R, E, C>
+  where T: Fn(A) -> Result<R, E>,
+        A: Eq + Hash + Clone,
+        R: Clone,
+        E: Clone,
+        C: Clock
+{
+  pub fn value(&mut self, arg: A) -> Result<R, E> {
+    let now = self.clock.now();
+
+    match self.value.get(&arg) {
+      Some(entry) if !entry.is_expired(now) => {
+        self.stats.hits += 1;
+        return entry.value.clone();
+      },
+      Some(_) => self.stats.expirations += 1,
+      None => (),
+    }
+    self.stats.misses += 1;
+
+    let result = self.calculate(&arg);
+
+    // retries may have taken a while, so the
+    // expiration starts counting once they are done
+    let ttl = match (&result, self.policy) {
+      (Ok(_), _) => self.ttl,
+      (Err(_), ErrorPolicy::CacheFor(ttl)) => Some(ttl),
+      (Err(_), _) => {
+        // an expired value that failed to be calculated again is dropped
+        self.value.remove(&arg);
+        return result;
+      },
+    };
+
+    let entry = Entry {
+      value: result.clone(),
+      expires_at: expiration(self.clock.now(), ttl),
+    };
+
+    if self.value.insert(arg, entry).is_some() {
+      self.stats.evictions += 1;
+    }
+
+    result
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::cell::Cell;
+
+  use crate::cacher::clock::ManualClock;
+
+  // fails the first `failures` times it's called
+  fn flaky(failures: u32, calls: &Cell<u32>) -> impl Fn(u32) -> Result<u32, String> + '_ {
+    move |num| {
+      calls.set(calls.get() + 1);
+      if calls.get() <= failures {
+        Err(format!("attempt {} failed", calls.get()))
+      } else {
+        Ok(num * 2)
+      }
+    }
+  }
+
+  #[test]
+  fn errors_are_not_cached() {
+    let calls = Cell::new(0);
+    let mut c = TryCacher::new(ErrorPolicy::NoCache, flaky(1, &calls));
+
+    assert_eq!(Err(String::from("attempt 1 failed")), c.value(2));
+    assert!(c.is_empty());
+    assert_eq!(Ok(4), c.value(2));
+    assert_eq!(Ok(4), c.value(2));
+
+    assert_eq!(2, calls.get());
+    assert_eq!(CacheStats { hits: 1, misses: 2, evictions: 0, expirations: 0 }, c.stats());
+  }
+
+  #[test]
+  fn errors_are_cached_for_a_while() {
+    let clock = ManualClock::new();
+    let calls = Cell::new(0);
+    let mut c = TryCacher::new(ErrorPolicy::CacheFor(Duration::from_secs(10)), flaky(1, &calls))
+      .with_clock(clock.clone());
+
+    assert!(c.value(2).is_err());
+    clock.advance(Duration::from_secs(9));
+    assert!(c.value(2).is_err());
+    assert_eq!(1, calls.get());
+
+    clock.advance(Duration::from_secs(1));
+    assert_eq!(Ok(4), c.value(2));
+    // successful values don't expire
+    clock.advance(Duration::from_secs(3600));
+    assert_eq!(Ok(4), c.value(2));
+
+    assert_eq!(2, calls.get());
+    assert_eq!(CacheStats { hits: 2, misses: 2, evictions: 0, expirations: 1 }, c.stats());
+  }
+
+  #[test]
+  fn retries_back_off() {
+    let clock = ManualClock::new();
+    let start = clock.now();
+    let calls = Cell::new(0);
+    let policy = ErrorPolicy::Retry { retries: 3, backoff: Duration::from_millis(100) };
+    let mut c = TryCacher::new(policy, flaky(2, &calls)).with_clock(clock.clone());
+
+    assert_eq!(Ok(4), c.value(2));
+
+    // waited 100ms before the second attempt and 200ms before the third
+    assert_eq!(3, calls.get());
+    assert_eq!(Duration::from_millis(300), clock.now() - start);
+    assert_eq!(1, c.stats().misses);
+  }
+
+  #[test]
+  fn retries_give_up() {
+    let clock = ManualClock::new();
+    let calls = Cell::new(0);
+    let policy = ErrorPolicy::Retry { retries: 2, backoff: Duration::from_millis(100) };
+    let mut c = TryCacher::new(policy, flaky(10, &calls)).with_clock(clock.clone());
+
+    assert_eq!(Err(String::from("attempt 3 failed")), c.value(2));
+    assert!(c.is_empty());
+
+    // the next call gets its own retries
+    assert!(c.value(2).is_err());
+    assert_eq!(6, calls.get());
+  }
+
+  #[test]
+  fn expired_values_that_fail_are_dropped() {
+    let clock = ManualClock::new();
+    let calls = Cell::new(0);
+    let mut c = TryCacher::new(ErrorPolicy::NoCache, |num: u32| {
+      calls.set(calls.get() + 1);
+      if calls.get() == 1 { Ok(num) } else { Err("down") }
+    })
+      .with_ttl(Duration::from_secs(60))
+      .with_clock(clock.clone());
+
+    assert_eq!(Ok(1), c.value(1));
+    clock.advance(Duration::from_secs(60));
+    assert_eq!(Err("down"), c.value(1));
+    assert!(c.is_empty());
+  }
+}
@@ -0,0 +1,270 @@
+/*
+  ** A map that remembers in which order its keys were used, so when it's
+  ** full the least recently used entry can be evicted to make room.
+  **
+  ** The entries live in a Vec and are linked to each other by their index,
+  ** forming a doubly linked list from the most to the least recently used.
+  ** The HashMap points every key to its index, so looking up, moving an
+  ** entry to the front and evicting from the back are all O(1).
+*/
+
+use std::collections::HashMap;
+use std::hash::Hash;
+
+struct Node<K, V> {
+  key: K,
+  value: V,
+  prev: Option<usize>,
+  next: Option<usize>,
+}
+
+pub struct Lru<K, V> {
+  index: HashMap<K, usize>,
+  // a slot is None after its entry was removed, until it's reused
+  nodes: Vec<Option<Node<K, V>>>,
+  free: Vec<usize>,
+  // most recently used
+  head: Option<usize>,
+  // least recently used
+  tail: Option<usize>,
+  // None means the map can grow forever
+  capacity: Option<usize>,
+}
+
+impl<K, V> Lru<K, V>
+  where K: Eq + Hash + Clone
+{
+  pub fn unbounded() -> Lru<K, V> {
+    Lru {
+      index: HashMap::new(),
+      nodes: Vec::new(),
+      free: Vec::new(),
+      head: None,
+      tail: None,
+      capacity: None,
+    }
+  }
+
+  // panics when capacity is 0
+  pub fn with_capacity(capacity: usize) -> Lru<K, V> {
+    assert!(capacity > 0, "an Lru needs room for at least one entry");
+
+    let mut lru = Lru::unbounded();
+    lru.capacity = Some(capacity);
+    lru
+  }
+
+  pub fn len(&self) -> usize {
+    self.index.len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.index.is_empty()
+  }
+
+  pub fn capacity(&self) -> Option<usize> {
+    self.capacity
+  }
+
+  pub fn contains(&self, key: &K) -> bool {
+    self.index.contains_key(key)
+  }
+
+  // getting a value marks it as the most recently used
+  pub fn get(&mut self, key: &K) -> Option<&V> {
+    let i = *self.index.get(key)?;
+    self.touch(i);
+    Some(&self.node(i).value)
+  }
+
+  pub fn get_mut(&mut self, key: &K) -> Option<&mut V> {
+    let i = *self.index.get(key)?;
+    self.touch(i);
+    Some(&mut self.node_mut(i).value)
+  }
+
+  // looks at a value without changing the order
+  pub fn peek(&self, key: &K) -> Option<&V> {
+    let i = *self.index.get(key)?;
+    Some(&self.node(i).value)
+  }
+
+  // returns the entry that was evicted to make room, if any
+  // replacing the value of a key that is already there never evicts
+  pub fn insert(&mut self, key: K, value: V) -> Option<(K, V)> {
+    if let Some(&i) = self.index.get(&key) {
+      self.node_mut(i).value = value;
+      self.touch(i);
+      return None;
+    }
+
+    let evicted = match self.capacity {
+      Some(capacity) if self.len() >= capacity => self.pop_lru(),
+      _ => None,
+    };
+
+    let node = Node {
+      key: key.clone(),
+      value,
+      prev: None,
+      next: None,
+    };
+
+    let i = match self.free.pop() {
+      Some(i) => {
+        self.nodes[i] = Some(node);
+        i
+      },
+      None => {
+        self.nodes.push(Some(node));
+        self.nodes.len() - 1
+      },
+    };
+
+    self.index.insert(key, i);
+    self.push_front(i);
+
+    evicted
+  }
+
+  pub fn remove(&mut self, key: &K) -> Option<V> {
+    let i = self.index.remove(key)?;
+    Some(self.take(i).1)
+  }
+
+  // removes the least recently used entry
+  pub fn pop_lru(&mut self) -> Option<(K, V)> {
+    let i = self.tail?;
+    let (key, value) = self.take(i);
+    self.index.remove(&key);
+    Some((key, value))
+  }
+
+  // keys from the most to the least recently used
+  pub fn keys(&self) -> Vec<&K> {
+    let mut keys = Vec::with_capacity(self.len());
+    let mut current = self.head;
+
+    while let Some(i) = current {
+      let node = self.node(i);
+      keys.push(&node.key);
+      current = node.next;
+    }
+
+    keys
+  }
+
+  // every slot in the index points to a Some node,
+  // so these unwraps can't fail
+  fn node(&self, i: usize) -> &Node<K, V> {
+    self.nodes[i].as_ref().unwrap()
+  }
+
+  fn node_mut(&mut self, i: usize) -> &mut Node<K, V> {
+    self.nodes[i].as_mut().unwrap()
+  }
+
+  fn touch(&mut self, i: usize) {
+    if self.head != Some(i) {
+      self.unlink(i);
+      self.push_front(i);
+    }
+  }
+
+  // takes the node out of its slot, the caller
+  // is the one that removes it from the index
+  fn take(&mut self, i: usize) -> (K, V) {
+    self.unlink(i);
+    self.free.push(i);
+
+    let node = self.nodes[i].take().unwrap();
+    (node.key, node.value)
+  }
+
+  fn unlink(&mut self, i: usize) {
+    let (prev, next) = {
+      let node = self.node(i);
+      (node.prev, node.next)
+    };
+
+    match prev {
+      Some(p) => self.node_mut(p).next = next,
+      None => self.head = next,
+    }
+
+    match next {
+      Some(n) => self.node_mut(n).prev = prev,
+      None => self.tail = prev,
+    }
+  }
+
+  fn push_front(&mut self, i: usize) {
+    let head = self.head;
+
+    {
+      let node = self.node_mut(i);
+      node.prev = None;
+      node.next = head;
+    }
+
+    match head {
+      Some(h) => self.node_mut(h).prev = Some(i),
+      None => self.tail = Some(i),
+    }
+
+    self.head = Some(i);
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn evicts_the_least_recently_used() {
+    let mut lru = Lru::with_capacity(2);
+
+    assert_eq!(None, lru.insert(1, "one"));
+    assert_eq!(None, lru.insert(2, "two"));
+
+    // using 1 makes 2 the least recently used
+    assert_eq!(Some(&"one"), lru.get(&1));
+    assert_eq!(Some((2, "two")), lru.insert(3, "three"));
+
+    assert_eq!(vec![&3, &1], lru.keys());
+    assert!(!lru.contains(&2));
+  }
+
+  #[test]
+  fn replacing_a_value_does_not_evict() {
+    let mut lru = Lru::with_capacity(2);
+    lru.insert(1, "one");
+    lru.insert(2, "two");
+
+    assert_eq!(None, lru.insert(1, "uno"));
+    assert_eq!(Some(&"uno"), lru.peek(&1));
+    assert_eq!(vec![&1, &2], lru.keys());
+  }
+
+  #[test]
+  fn removed_slots_are_reused() {
+    let mut lru = Lru::unbounded();
+    lru.insert(1, "one");
+    lru.insert(2, "two");
+    lru.insert(3, "three");
+
+    assert_eq!(Some("two"), lru.remove(&2));
+    lru.insert(4, "four");
+
+    assert_eq!(3, lru.nodes.len());
+    assert_eq!(vec![&4, &3, &1], lru.keys());
+    assert_eq!(Some((1, "one")), lru.pop_lru());
+    assert_eq!(2, lru.len());
+  }
+
+  #[test]
+  #[should_panic]
+  fn zero_capacity_panics() {
+    Lru::<u32, u32>::with_capacity(0);
+  }
+}
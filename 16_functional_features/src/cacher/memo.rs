@@ -0,0 +1,131 @@
+/*
+  ** What the functions generated by #[memoize] keep their results in. It's
+  ** only public so the generated code can name it, it isn't meant to be
+  ** used directly.
+  **
+  ** A Cacher behind a Mutex, plus the arguments that are being calculated
+  ** right now. The lock isn't held while the function runs, so it can call
+  ** itself with other arguments, and a thread asking for arguments that
+  ** another thread is already calculating waits for that result instead of
+  ** calculating it again, the same single-flight SyncCacher does.
+  **
+  ** A function that ends up asking for the arguments it's calculating
+  ** panics, instead of waiting for itself forever.
+*/
+
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::sync::{Arc, Mutex};
+
+use super::clock::{Clock, SystemClock};
+use super::sync::{Flight, FlightState};
+use super::Cacher;
+
+struct State<A, R, C>
+  where A: Eq + Hash + Clone,
+        C: Clock
+{
+  cacher: Cacher<fn(A) -> R, A, R, C>,
+  flights: HashMap<A, Arc<Flight<R>>>,
+}
+
+pub struct Memo<A, R, C = SystemClock>
+  where A: Eq + Hash + Clone,
+        C: Clock
+{
+  state: Mutex<State<A, R, C>>,
+}
+
+impl<A, R, C> Memo<A, R, C>
+  where A: Eq + Hash + Clone,
+        R: Clone,
+        C: Clock
+{
+  pub fn new(cacher: Cacher<fn(A) -> R, A, R, C>) -> Memo<A, R, C> {
+    Memo {
+      state: Mutex::new(State { cacher, flights: HashMap::new() }),
+    }
+  }
+
+  pub fn value(&self, arg: A) -> R {
+    loop {
+      let mut state = self.state.lock().unwrap();
+
+      if let Some(v) = state.cacher.cached(&arg) {
+        return v.clone();
+      }
+
+      let flight = match state.flights.entry(arg.clone()) {
+        Entry::Occupied(entry) => Arc::clone(entry.get()),
+        Entry::Vacant(entry) => {
+          let flight = Arc::clone(entry.insert(Arc::new(Flight::new())));
+          // a fn is Copy, so it can still be called once the lock is released
+          let calculation = state.cacher.calculation;
+          drop(state);
+
+          return self.calculate(calculation, arg, flight);
+        },
+      };
+
+      // the lock has to be released before waiting
+      // or the calculating thread could never store its value
+      drop(state);
+
+      if let Some(v) = flight.wait() {
+        return v;
+      }
+    }
+  }
+
+  fn calculate(&self, calculation: fn(A) -> R, arg: A, flight: Arc<Flight<R>>) -> R {
+    let mut guard = FlightGuard {
+      memo: self,
+      arg: &arg,
+      flight: &flight,
+      finished: false,
+    };
+
+    let v = calculation(arg.clone());
+
+    {
+      let mut state = self.state.lock().unwrap();
+      state.flights.remove(&arg);
+      state.cacher.store(arg.clone(), v.clone());
+    }
+    flight.finish(FlightState::Done(v.clone()));
+    guard.finished = true;
+
+    v
+  }
+}
+
+// like the one in sync.rs, if the function panics this takes the flight
+// out and wakes up whoever was waiting on it
+struct FlightGuard<'a, A, R, C>
+  where A: Eq + Hash + Clone,
+        R: Clone,
+        C: Clock
+{
+  memo: &'a Memo<A, R, C>,
+  arg: &'a A,
+  flight: &'a Arc<Flight<R>>,
+  finished: bool,
+}
+
+impl<'a, A, R, C> Drop for FlightGuard<'a, A, R, C>
+  where A: Eq + Hash + Clone,
+        R: Clone,
+        C: Clock
+{
+  fn drop(&mut self) {
+    if self.finished {
+      return;
+    }
+
+    if let Ok(mut state) = self.memo.state.lock() {
+      state.flights.remove(self.arg);
+    }
+    self.flight.finish(FlightState::Failed);
+  }
+}
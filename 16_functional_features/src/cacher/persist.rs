@@ -0,0 +1,497 @@
+/*
+  ** Keeps the values a Cacher calculated in a file, so they survive the
+  ** process and don't have to be calculated slowly again after a restart.
+  **
+  ** The file is a log: every calculated value is appended to it as a line
+  ** holding a checksum and the JSON of the argument, the value and when the
+  ** value expires. An Instant only means something inside the process that
+  ** took it, so the expiration is saved as wall clock time, in milliseconds
+  ** since the Unix epoch, and values that expired while the process wasn't
+  ** running are left out when the log is opened again. Lines are
+  ** never changed in place, so when a value is calculated again the old line
+  ** is just left behind. Once there are too many of those the log is
+  ** compacted: the values still in the cache are written to a new file that
+  ** then takes the place of the old one.
+  **
+  ** A line that was cut short by a crash or changed on disk doesn't match its
+  ** checksum. It's skipped when the log is opened and its value is simply
+  ** calculated again the next time it's asked for.
+*/
+
+use std::convert::TryFrom;
+use std::ffi::OsString;
+use std::fs::{self, File, OpenOptions};
+use std::hash::Hash;
+use std::io::{self, BufWriter, ErrorKind, Write};
+use std::marker::PhantomData;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use super::clock::{Clock, SystemClock};
+use super::lru::Lru;
+use super::{Cacher, Entry};
+
+// a log with few lines is never worth compacting
+const MIN_RECORDS: usize = 64;
+
+// what the Cacher needs from its file, without knowing
+// that A and R have to be serializable to get there
+pub(crate) trait Backing<A, R> {
+  fn append(&mut self, arg: &A, value: &R, expires_at: Option<SystemTime>) -> io::Result<()>;
+  // replaces everything in the file with these entries
+  fn rewrite(&mut self, entries: &[(&A, &R, Option<SystemTime>)]) -> io::Result<()>;
+  // lines in the file, including the ones left behind
+  fn records(&self) -> usize;
+}
+
+// true when most of the log is lines that were left behind
+pub(crate) fn needs_compaction(records: usize, live: usize) -> bool {
+  records > MIN_RECORDS.max(live * 2)
+}
+
+struct Log<A, R> {
+  path: PathBuf,
+  file: File,
+  records: usize,
+  types: PhantomData<fn(&A, &R)>,
+}
+
+// what was read back when the log was opened
+struct Loaded<A, R> {
+  entries: Vec<(A, R, Option<SystemTime>)>,
+  // lines that didn't match their checksum or couldn't be parsed
+  corrupted: usize,
+  // lines whose argument showed up again later in the log
+  superseded: usize,
+  // lines whose value had already expired
+  expired: usize,
+}
+
+impl<A, R> Log<A, R>
+  where A: Eq + Hash + Clone + Serialize + DeserializeOwned,
+        R: Serialize + DeserializeOwned
+{
+  fn open(path: &Path, now: SystemTime) -> io::Result<(Log<A, R>, Loaded<A, R>)> {
+    let bytes = match fs::read(path) {
+      Ok(bytes) => bytes,
+      Err(e) if e.kind() == ErrorKind::NotFound => Vec::new(),
+      Err(e) => return Err(e),
+    };
+
+    let loaded = load(&bytes, now);
+    let mut log = Log {
+      path: path.to_path_buf(),
+      file: OpenOptions::new().create(true).append(true).open(path)?,
+      records: loaded.entries.len() + loaded.superseded + loaded.expired,
+      types: PhantomData,
+    };
+
+    // leaving bad lines behind would have them counted
+    // as corrupted again on every open
+    if loaded.corrupted > 0 || loaded.superseded > 0 || loaded.expired > 0 {
+      let entries: Vec<_> = loaded.entries.iter().map(|(a, r, at)| (a, r, *at)).collect();
+      log.rewrite(&entries)?;
+    }
+
+    Ok((log, loaded))
+  }
+}
+
+impl<A, R> Backing<A, R> for Log<A, R>
+  where A: Serialize,
+        R: Serialize
+{
+  fn append(&mut self, arg: &A, value: &R, expires_at: Option<SystemTime>) -> io::Result<()> {
+    // a whole line goes out in a single write, so a crash
+    // can only leave the last line of the log cut short
+    self.file.write_all(&record(arg, value, expires_at)?)?;
+    self.records += 1;
+    Ok(())
+  }
+
+  fn rewrite(&mut self, entries: &[(&A, &R, Option<SystemTime>)]) -> io::Result<()> {
+    let mut tmp = OsString::from(self.path.as_os_str());
+    tmp.push(".compact");
+    let tmp = PathBuf::from(tmp);
+
+    {
+      let mut out = BufWriter::new(File::create(&tmp)?);
+      for (arg, value, expires_at) in entries {
+        out.write_all(&record(arg, value, *expires_at)?)?;
+      }
+      out.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+    }
+
+    // renaming is atomic, a crash leaves either the old or the new log
+    fs::rename(&tmp, &self.path)?;
+    self.file = OpenOptions::new().append(true).open(&self.path)?;
+    self.records = entries.len();
+    Ok(())
+  }
+
+  fn records(&self) -> usize {
+    self.records
+  }
+}
+
+// a line of the log: the checksum of the JSON in hex, a space and the JSON
+// JSON escapes newlines inside strings, so a record is always a single line
+fn record<A, R>(arg: &A, value: &R, expires_at: Option<SystemTime>) -> io::Result<Vec<u8>>
+  where A: Serialize,
+        R: Serialize
+{
+  let json = serde_json::to_vec(&(arg, value, expires_at.map(to_millis)))?;
+
+  let mut line = format!("{:016x} ", checksum(&json)).into_bytes();
+  line.extend_from_slice(&json);
+  line.push(b'\n');
+  Ok(line)
+}
+
+fn load<A, R>(bytes: &[u8], now: SystemTime) -> Loaded<A, R>
+  where A: Eq + Hash + Clone + DeserializeOwned,
+        R: DeserializeOwned
+{
+  // an Lru is used as an insertion ordered map, a later line
+  // for the same argument replaces the value of the earlier one
+  let mut entries = Lru::unbounded();
+  let mut loaded = Loaded { entries: Vec::new(), corrupted: 0, superseded: 0, expired: 0 };
+
+  for line in bytes.split(|b| *b == b'\n').filter(|line| !line.is_empty()) {
+    match parse::<A, R>(line) {
+      Some((arg, value, expires_at)) => {
+        if entries.contains(&arg) {
+          loaded.superseded += 1;
+        }
+        entries.insert(arg, (value, expires_at.and_then(from_millis)));
+      },
+      None => loaded.corrupted += 1,
+    }
+  }
+
+  while let Some((arg, (value, expires_at))) = entries.pop_lru() {
+    match expires_at {
+      Some(at) if at <= now => loaded.expired += 1,
+      _ => loaded.entries.push((arg, value, expires_at)),
+    }
+  }
+  loaded
+}
+
+fn parse<A, R>(line: &[u8]) -> Option<(A, R, Option<u64>)>
+  where A: DeserializeOwned,
+        R: DeserializeOwned
+{
+  if line.len() < 17 || line[16] != b' ' {
+    return None;
+  }
+
+  let (sum, json) = (&line[..16], &line[17..]);
+  let sum = u64::from_str_radix(std::str::from_utf8(sum).ok()?, 16).ok()?;
+
+  if sum != checksum(json) {
+    return None;
+  }
+
+  serde_json::from_slice(json).ok()
+}
+
+// times before the epoch can't come up, an expiration is always in the future
+fn to_millis(at: SystemTime) -> u64 {
+  let since = at.duration_since(UNIX_EPOCH).unwrap_or_default();
+  u64::try_from(since.as_millis()).unwrap_or(u64::MAX)
+}
+
+// a time too far away to be represented just never comes, like a ttl
+// too big to be added to now
+fn from_millis(millis: u64) -> Option<SystemTime> {
+  UNIX_EPOCH.checked_add(Duration::from_millis(millis))
+}
+
+// 64 bit FNV-1a, good enough to tell a damaged line apart
+fn checksum(bytes: &[u8]) -> u64 {
+  bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, b| {
+    (hash ^ u64::from(*b)).wrapping_mul(0x0100_0000_01b3)
+  })
+}
+
+impl<T, A, R> Cacher<T, A, R>
+  where T: Fn(A) -> R,
+        A: Eq + Hash + Clone + Serialize + DeserializeOwned + 'static,
+        R: Serialize + DeserializeOwned + 'static
+{
+  // an unbounded cacher that saves its values to the log at path,
+  // the same as Cacher::new(calculation).persist(path)
+  pub fn open<P: AsRef<Path>>(path: P, calculation: T) -> io::Result<Cacher<T, A, R>> {
+    Cacher::open_with_clock(path, calculation, SystemClock)
+  }
+}
+
+impl<T, A, R, C> Cacher<T, A, R, C>
+  where T: Fn(A) -> R,
+        A: Eq + Hash + Clone + Serialize + DeserializeOwned + 'static,
+        R: Serialize + DeserializeOwned + 'static,
+        C: Clock
+{
+  // like open, but reading the time from clock, which has to be set before
+  // the log is read since the expirations in it are turned into its times
+  pub fn open_with_clock<P>(path: P, calculation: T, clock: C) -> io::Result<Cacher<T, A, R, C>>
+    where P: AsRef<Path>
+  {
+    Cacher::new(calculation).with_clock(clock).persist(path)
+  }
+
+  /*
+    ** Starts with every value found in the log at path that didn't expire,
+    ** creating the log if it doesn't exist, and appends every value it
+    ** calculates from now on:
+    **
+    **   let c = Cacher::with_capacity(100, calculation)
+    **     .with_ttl(Duration::from_secs(60))
+    **     .persist("cache.log")?;
+    **
+    ** Call it after with_clock, or use open_with_clock, the expirations
+    ** read from the log are turned into times of the cacher's clock. A cacher with a capacity
+    ** keeps the values that were used last. If writing to the log ever
+    ** fails the cacher stops using it and keeps working in memory, and
+    ** log_error tells what went wrong.
+  */
+  pub fn persist<P: AsRef<Path>>(mut self, path: P) -> io::Result<Cacher<T, A, R, C>> {
+    let wall_now = SystemTime::now();
+    let (log, loaded) = Log::open(path.as_ref(), wall_now)?;
+
+    let now = self.clock.now();
+    for (arg, value, expires_at) in loaded.entries {
+      // the values that already expired were left out of loaded
+      let expires_at = expires_at.and_then(|at| {
+        now.checked_add(at.duration_since(wall_now).unwrap_or_default())
+      });
+      self.value.insert(arg, Entry { value, expires_at });
+    }
+
+    self.log = Some(Box::new(log));
+    self.log_error = None;
+    Ok(self)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::cell::Cell;
+  use std::env;
+  use std::process;
+
+  use crate::cacher::clock::ManualClock;
+  use crate::cacher::CacheStats;
+
+  // a path of its own for every test, removed when the test ends
+  struct TempLog(PathBuf);
+
+  impl TempLog {
+    fn new(name: &str) -> TempLog {
+      let path = env::temp_dir().join(format!("cacher-{}-{}.log", process::id(), name));
+      let _ = fs::remove_file(&path);
+      TempLog(path)
+    }
+  }
+
+  impl Drop for TempLog {
+    fn drop(&mut self) {
+      let _ = fs::remove_file(&self.0);
+    }
+  }
+
+  fn lines(path: &Path) -> usize {
+    fs::read_to_string(path).unwrap().lines().count()
+  }
+
+  #[test]
+  fn values_survive_reopening() {
+    let log = TempLog::new("reopen");
+    let calls = Cell::new(0);
+    let double = |num: u32| {
+      calls.set(calls.get() + 1);
+      num * 2
+    };
+
+    {
+      let mut c = Cacher::open(&log.0, double).unwrap();
+      assert_eq!(4, c.value(2));
+      assert_eq!(6, c.value(3));
+    }
+
+    let mut c = Cacher::open(&log.0, double).unwrap();
+    assert_eq!(4, c.value(2));
+    assert_eq!(6, c.value(3));
+
+    assert_eq!(2, calls.get());
+    assert_eq!(CacheStats { hits: 2, ..CacheStats::default() }, c.stats());
+  }
+
+  #[test]
+  fn corrupted_lines_are_calculated_again() {
+    let log = TempLog::new("corrupt");
+
+    {
+      let mut c = Cacher::open(&log.0, |name: String| name.len()).unwrap();
+      c.value(String::from("bob"));
+      c.value(String::from("alice"));
+    }
+
+    // change the value of the first line and cut the second one short
+    let text = fs::read_to_string(&log.0).unwrap().replace(",3,", ",4,");
+    fs::write(&log.0, &text[..text.len() - 4]).unwrap();
+
+    let calls = Cell::new(0);
+    let mut c = Cacher::open(&log.0, |name: String| {
+      calls.set(calls.get() + 1);
+      name.len()
+    }).unwrap();
+
+    assert!(c.is_empty());
+    assert_eq!(3, c.value(String::from("bob")));
+    assert_eq!(5, c.value(String::from("alice")));
+    assert_eq!(2, calls.get());
+    // the bad lines were dropped when the log was opened
+    assert_eq!(2, lines(&log.0));
+  }
+
+  #[test]
+  fn later_lines_win_and_old_ones_are_dropped() {
+    let log = TempLog::new("supersede");
+
+    {
+      let mut c = Cacher::open(&log.0, |num: u32| num + 1).unwrap();
+      for num in 0..10 {
+        c.value(num);
+      }
+    }
+
+    // 0 was calculated again by some other process
+    OpenOptions::new().append(true).open(&log.0).unwrap()
+      .write_all(&record(&0u32, &7u32, None).unwrap()).unwrap();
+    assert_eq!(11, lines(&log.0));
+
+    let mut c = Cacher::open(&log.0, |num: u32| num + 1).unwrap();
+    assert_eq!(10, lines(&log.0));
+    assert_eq!(7, c.value(0));
+  }
+
+  #[test]
+  fn appending_compacts_a_log_of_old_lines() {
+    let log = TempLog::new("compact");
+    let clock = ManualClock::new();
+    let mut c = Cacher::open_with_clock(&log.0, |num: u32| num, clock.clone())
+      .unwrap()
+      .with_ttl(Duration::from_secs(1));
+
+    // every expired value is appended again
+    for _ in 0..=MIN_RECORDS {
+      c.value(1);
+      clock.advance(Duration::from_secs(1));
+    }
+
+    assert_eq!(1, lines(&log.0));
+    assert_eq!(MIN_RECORDS as u64, c.stats().expirations);
+  }
+
+  #[test]
+  fn expirations_survive_reopening() {
+    let log = TempLog::new("expire");
+
+    {
+      let mut c = Cacher::new(|num: u32| num * 2)
+        .with_ttl(Duration::from_secs(60))
+        .persist(&log.0)
+        .unwrap();
+      c.value(1);
+    }
+
+    // the value is still fresh, but only for the rest of its minute
+    let clock = ManualClock::new();
+    let mut c = Cacher::open_with_clock(&log.0, |num: u32| num * 3, clock.clone()).unwrap();
+    assert_eq!(2, c.value(1));
+
+    clock.advance(Duration::from_secs(60));
+    assert_eq!(3, c.value(1));
+    assert_eq!(1, c.stats().expirations);
+  }
+
+  #[test]
+  fn values_that_expired_while_closed_are_dropped() {
+    let log = TempLog::new("expired");
+    let now = SystemTime::now();
+    let hour = Duration::from_secs(60 * 60);
+
+    let mut bytes = Vec::new();
+    bytes.extend(record(&1u32, &10u32, Some(now - hour)).unwrap());
+    bytes.extend(record(&2u32, &20u32, Some(now + hour)).unwrap());
+    bytes.extend(record(&3u32, &30u32, None).unwrap());
+    fs::write(&log.0, bytes).unwrap();
+
+    let mut c = Cacher::open(&log.0, |num: u32| num).unwrap();
+    assert_eq!(2, c.len());
+    assert_eq!(2, lines(&log.0));
+    assert_eq!(1, c.value(1));
+    assert_eq!(20, c.value(2));
+    assert_eq!(30, c.value(3));
+  }
+
+  #[test]
+  fn a_bounded_cacher_keeps_the_last_values() {
+    let log = TempLog::new("bounded");
+
+    {
+      let mut c = Cacher::open(&log.0, |num: u32| num).unwrap();
+      for num in 0..5 {
+        c.value(num);
+      }
+    }
+
+    let calls = Cell::new(0);
+    let mut c = Cacher::with_capacity(2, |num: u32| {
+      calls.set(calls.get() + 1);
+      num
+    }).persist(&log.0).unwrap();
+
+    assert_eq!(2, c.len());
+    c.value(3);
+    c.value(4);
+    assert_eq!(0, calls.get());
+    c.value(0);
+    assert_eq!(1, calls.get());
+  }
+
+  struct BrokenLog;
+
+  impl<A, R> Backing<A, R> for BrokenLog {
+    fn append(&mut self, _: &A, _: &R, _: Option<SystemTime>) -> io::Result<()> {
+      Err(io::Error::other("disk full"))
+    }
+
+    fn rewrite(&mut self, _: &[(&A, &R, Option<SystemTime>)]) -> io::Result<()> {
+      Err(io::Error::other("disk full"))
+    }
+
+    fn records(&self) -> usize {
+      0
+    }
+  }
+
+  #[test]
+  fn a_failed_write_is_kept() {
+    let mut c = Cacher::new(|num: u32| num + 1);
+    c.log = Some(Box::new(BrokenLog));
+    assert!(c.log_error().is_none());
+
+    // the value is still calculated and cached
+    assert_eq!(2, c.value(1));
+    assert_eq!(2, c.value(1));
+    assert!(c.log.is_none());
+    assert_eq!("disk full", c.log_error().unwrap().to_string());
+  }
+}
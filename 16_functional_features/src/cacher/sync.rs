@@ -0,0 +1,313 @@
+/*
+  ** A Cacher that can be shared between threads behind an Arc.
+  **
+  ** The values are split between a few shards, each one with its own Mutex,
+  ** so threads asking for unrelated keys rarely wait on the same lock.
+  ** The calculation runs without holding any lock: the first thread asking
+  ** for a key leaves a "flight" in its place and the threads that ask for the
+  ** same key while it's being calculated wait on it instead of running the
+  ** expensive calculation again (this is known as single-flight).
+  **
+  ** A calculation that asks for the key it's calculating would wait for
+  ** itself forever, so it panics instead.
+*/
+
+use std::collections::hash_map::RandomState;
+use std::collections::HashMap;
+use std::hash::{BuildHasher, Hash};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::{self, ThreadId};
+
+use super::CacheStats;
+
+const DEFAULT_SHARDS: usize = 16;
+
+// shared with the functions #[memoize] generates, see memo.rs
+pub(super) enum FlightState<R> {
+  Running,
+  Done(R),
+  // the calculation panicked, whoever was waiting has to try again
+  Failed,
+}
+
+pub(super) struct Flight<R> {
+  state: Mutex<FlightState<R>>,
+  done: Condvar,
+  // the thread running the calculation
+  owner: ThreadId,
+}
+
+impl<R: Clone> Flight<R> {
+  pub(super) fn new() -> Flight<R> {
+    Flight {
+      state: Mutex::new(FlightState::Running),
+      done: Condvar::new(),
+      owner: thread::current().id(),
+    }
+  }
+
+  pub(super) fn finish(&self, state: FlightState<R>) {
+    *self.state.lock().unwrap() = state;
+    self.done.notify_all();
+  }
+
+  // blocks until the calculation finished, None if it panicked
+  // panics when called from inside the calculation, which could never finish
+  pub(super) fn wait(&self) -> Option<R> {
+    assert!(
+      self.owner != thread::current().id(),
+      "recursive call for the same key: the calculation asked for the value it is calculating"
+    );
+
+    let mut state = self.state.lock().unwrap();
+
+    loop {
+      match &*state {
+        FlightState::Running => state = self.done.wait(state).unwrap(),
+        FlightState::Done(v) => return Some(v.clone()),
+        FlightState::Failed => return None,
+      }
+    }
+  }
+}
+
+enum Slot<R> {
+  Ready(R),
+  Pending(Arc<Flight<R>>),
+}
+
+type Shard<A, R> = Mutex<HashMap<A, Slot<R>>>;
+
+pub struct SyncCacher<T, A, R>
+  where T: Fn(A) -> R,
+        A: Eq + Hash + Clone,
+        R: Clone
+{
+  calculation: T,
+  shards: Vec<Shard<A, R>>,
+  hasher: RandomState,
+  hits: AtomicU64,
+  misses: AtomicU64,
+}
+
+impl<T, A, R> SyncCacher<T, A, R>
+  where T: Fn(A) -> R,
+        A: Eq + Hash + Clone,
+        R: Clone
+{
+  pub fn new(calculation: T) -> SyncCacher<T, A, R> {
+    SyncCacher::with_shards(DEFAULT_SHARDS, calculation)
+  }
+
+  pub fn with_shards(shards: usize, calculation: T) -> SyncCacher<T, A, R> {
+    assert!(shards > 0, "a SyncCacher needs at least one shard");
+
+    SyncCacher {
+      calculation,
+      shards: (0..shards).map(|_| Mutex::new(HashMap::new())).collect(),
+      hasher: RandomState::new(),
+      hits: AtomicU64::new(0),
+      misses: AtomicU64::new(0),
+    }
+  }
+
+  // takes &self, so many threads can call it at the same time
+  pub fn value(&self, arg: A) -> R {
+    let shard = self.shard(&arg);
+
+    loop {
+      let mut map = shard.lock().unwrap();
+
+      match map.get(&arg) {
+        Some(Slot::Ready(v)) => {
+          self.hits.fetch_add(1, Ordering::Relaxed);
+          return v.clone();
+        },
+        Some(Slot::Pending(flight)) => {
+          let flight = Arc::clone(flight);
+          // the lock has to be released before waiting
+          // or the calculating thread could never store its value
+          drop(map);
+
+          if let Some(v) = flight.wait() {
+            self.hits.fetch_add(1, Ordering::Relaxed);
+            return v;
+          }
+        },
+        None => {
+          let flight = Arc::new(Flight::new());
+          map.insert(arg.clone(), Slot::Pending(Arc::clone(&flight)));
+          drop(map);
+
+          self.misses.fetch_add(1, Ordering::Relaxed);
+          return self.calculate(shard, arg, flight);
+        },
+      }
+    }
+  }
+
+  pub fn stats(&self) -> CacheStats {
+    CacheStats {
+      hits: self.hits.load(Ordering::Relaxed),
+      misses: self.misses.load(Ordering::Relaxed),
+      ..CacheStats::default()
+    }
+  }
+
+  fn shard(&self, arg: &A) -> &Shard<A, R> {
+    let hash = self.hasher.hash_one(arg);
+    &self.shards[(hash % self.shards.len() as u64) as usize]
+  }
+
+  fn calculate(&self, shard: &Shard<A, R>, arg: A, flight: Arc<Flight<R>>) -> R {
+    let mut guard = FlightGuard {
+      shard,
+      arg: &arg,
+      flight: &flight,
+      finished: false,
+    };
+
+    let v = (self.calculation)(arg.clone());
+
+    shard.lock().unwrap().insert(arg.clone(), Slot::Ready(v.clone()));
+    flight.finish(FlightState::Done(v.clone()));
+    guard.finished = true;
+
+    v
+  }
+}
+
+// if the calculation panics this is dropped while unwinding,
+// it takes the flight out of the shard and wakes up whoever was waiting on it
+struct FlightGuard<'a, A, R>
+  where A: Eq + Hash,
+        R: Clone
+{
+  shard: &'a Shard<A, R>,
+  arg: &'a A,
+  flight: &'a Arc<Flight<R>>,
+  finished: bool,
+}
+
+impl<'a, A, R> Drop for FlightGuard<'a, A, R>
+  where A: Eq + Hash,
+        R: Clone
+{
+  fn drop(&mut self) {
+    if self.finished {
+      return;
+    }
+
+    if let Ok(mut map) = self.shard.lock() {
+      map.remove(self.arg);
+    }
+    self.flight.finish(FlightState::Failed);
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::sync::atomic::AtomicUsize;
+  use std::sync::{mpsc, Barrier, OnceLock};
+  use std::time::Duration;
+
+  type Countdown = SyncCacher<fn(u32) -> u32, u32, u32>;
+
+  static RECURSIVE: OnceLock<Countdown> = OnceLock::new();
+
+  fn countdown(num: u32) -> u32 {
+    // 3 asks for 2, 2 for 1 and 1 asks for 3 again
+    let next = if num == 1 { 3 } else { num - 1 };
+    RECURSIVE.get_or_init(|| SyncCacher::new(countdown as fn(u32) -> u32)).value(next)
+  }
+
+  #[test]
+  #[should_panic(expected = "recursive call for the same key")]
+  fn asking_for_the_key_being_calculated_panics() {
+    countdown(3);
+  }
+
+  #[test]
+  fn concurrent_callers_share_one_calculation() {
+    let calls = Arc::new(AtomicUsize::new(0));
+    let counter = Arc::clone(&calls);
+
+    let cacher = Arc::new(SyncCacher::new(move |num: u32| {
+      counter.fetch_add(1, Ordering::SeqCst);
+      thread::sleep(Duration::from_millis(50));
+      num * 2
+    }));
+
+    let barrier = Arc::new(Barrier::new(8));
+    let mut handles = vec![];
+
+    for _ in 0..8 {
+      let cacher = Arc::clone(&cacher);
+      let barrier = Arc::clone(&barrier);
+
+      handles.push(thread::spawn(move || {
+        barrier.wait();
+        cacher.value(21)
+      }));
+    }
+
+    for handle in handles {
+      assert_eq!(42, handle.join().unwrap());
+    }
+
+    assert_eq!(1, calls.load(Ordering::SeqCst));
+    assert_eq!(CacheStats { hits: 7, misses: 1, ..CacheStats::default() }, cacher.stats());
+  }
+
+  #[test]
+  fn unrelated_keys_do_not_wait_on_each_other() {
+    let (release, blocked) = mpsc::channel::<()>();
+    let blocked = Mutex::new(blocked);
+
+    let cacher = Arc::new(SyncCacher::with_shards(1, move |num: u32| {
+      // 1 only finishes once the main thread got the value for 2
+      if num == 1 {
+        blocked.lock().unwrap().recv().unwrap();
+      }
+      num
+    }));
+
+    let slow = {
+      let cacher = Arc::clone(&cacher);
+      thread::spawn(move || cacher.value(1))
+    };
+
+    // even with a single shard, 2 doesn't wait for 1 to be calculated
+    while cacher.stats().misses == 0 {
+      thread::yield_now();
+    }
+    assert_eq!(2, cacher.value(2));
+
+    release.send(()).unwrap();
+    assert_eq!(1, slow.join().unwrap());
+  }
+
+  #[test]
+  fn a_panicking_calculation_can_be_retried() {
+    let fail = Arc::new(Mutex::new(true));
+    let should_fail = Arc::clone(&fail);
+
+    let cacher = Arc::new(SyncCacher::new(move |num: u32| {
+      if *should_fail.lock().unwrap() {
+        panic!("calculation failed");
+      }
+      num
+    }));
+
+    let failed = {
+      let cacher = Arc::clone(&cacher);
+      thread::spawn(move || cacher.value(1)).join()
+    };
+    assert!(failed.is_err());
+
+    *fail.lock().unwrap() = false;
+    assert_eq!(1, cacher.value(1));
+  }
+}
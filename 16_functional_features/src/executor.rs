@@ -0,0 +1,131 @@
+/*
+  ** Just enough of an async runtime to drive the AsyncCacher without pulling
+  ** in a real one: block_on runs a future on the current thread, parking it
+  ** until a waker says there's something new to poll.
+  **
+  ** join_all and yield_now are there to run a few futures at the same time,
+  ** which is what's needed to see concurrent requests being deduplicated.
+*/
+
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll, Wake, Waker};
+use std::thread::{self, Thread};
+
+// waking the future unparks the thread that is blocked on it
+struct ThreadWaker(Thread);
+
+impl Wake for ThreadWaker {
+  fn wake(self: Arc<Self>) {
+    self.0.unpark();
+  }
+}
+
+pub fn block_on<F: Future>(future: F) -> F::Output {
+  let mut future = Box::pin(future);
+  let waker = Waker::from(Arc::new(ThreadWaker(thread::current())));
+  let mut cx = Context::from_waker(&waker);
+
+  loop {
+    match future.as_mut().poll(&mut cx) {
+      Poll::Ready(output) => return output,
+      // an unpark that came before the park makes it return right
+      // away, so a wake up while polling isn't lost
+      Poll::Pending => thread::park(),
+    }
+  }
+}
+
+// polls every future in turn until all of them are done,
+// the outputs come back in the same order as the futures
+pub fn join_all<F: Future>(futures: Vec<F>) -> JoinAll<F> {
+  JoinAll {
+    outputs: futures.iter().map(|_| None).collect(),
+    futures: futures.into_iter().map(|f| Some(Box::pin(f))).collect(),
+  }
+}
+
+pub struct JoinAll<F: Future> {
+  // None once the future finished
+  futures: Vec<Option<Pin<Box<F>>>>,
+  outputs: Vec<Option<F::Output>>,
+}
+
+// the futures are boxed, so JoinAll can be moved around even while they're pinned
+impl<F: Future> Unpin for JoinAll<F> {}
+
+impl<F: Future> Future for JoinAll<F> {
+  type Output = Vec<F::Output>;
+
+  fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Vec<F::Output>> {
+    let this = &mut *self;
+
+    for (future, output) in this.futures.iter_mut().zip(this.outputs.iter_mut()) {
+      if let Some(f) = future {
+        if let Poll::Ready(v) = f.as_mut().poll(cx) {
+          *output = Some(v);
+          *future = None;
+        }
+      }
+    }
+
+    if this.futures.iter().all(Option::is_none) {
+      Poll::Ready(this.outputs.iter_mut().map(|o| o.take().unwrap()).collect())
+    } else {
+      Poll::Pending
+    }
+  }
+}
+
+// gives the other futures a turn before going on
+pub fn yield_now() -> YieldNow {
+  YieldNow { yielded: false }
+}
+
+pub struct YieldNow {
+  yielded: bool,
+}
+
+impl Future for YieldNow {
+  type Output = ();
+
+  fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<()> {
+    if self.yielded {
+      return Poll::Ready(());
+    }
+
+    self.yielded = true;
+    cx.waker().wake_by_ref();
+    Poll::Pending
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::cell::RefCell;
+
+  #[test]
+  fn block_on_a_value() {
+    assert_eq!(3, block_on(async { 1 + 2 }));
+  }
+
+  #[test]
+  fn join_all_takes_turns() {
+    let order = RefCell::new(Vec::new());
+
+    let task = |name: &'static str| {
+      let order = &order;
+      async move {
+        order.borrow_mut().push(name);
+        yield_now().await;
+        order.borrow_mut().push(name);
+        name
+      }
+    };
+
+    assert_eq!(vec!["a", "b"], block_on(join_all(vec![task("a"), task("b")])));
+    assert_eq!(vec!["a", "b", "a", "b"], *order.borrow());
+  }
+}
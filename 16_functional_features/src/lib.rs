@@ -0,0 +1,197 @@
+//! `#[memoize]` remembers what a free function returned for its arguments:
+//!
+//! ```
+//! use functional::memoize;
+//!
+//! #[memoize(capacity = 10)]
+//! fn double(n: u32) -> u32 {
+//!   n * 2
+//! }
+//!
+//! assert_eq!(4, double(2));
+//! ```
+//!
+//! A cache with no room for any value is refused:
+//!
+//! ```compile_fail
+//! use functional::memoize;
+//!
+//! #[memoize(capacity = 0)]
+//! fn double(n: u32) -> u32 {
+//!   n * 2
+//! }
+//! ```
+//!
+//! So are generic functions, since a static can't be generic:
+//!
+//! ```compile_fail
+//! use functional::memoize;
+//!
+//! #[memoize]
+//! fn same<T: Clone>(value: T) -> T {
+//!   value
+//! }
+//! ```
+//!
+//! And methods, whose results depend on more than their arguments:
+//!
+//! ```compile_fail
+//! use functional::memoize;
+//!
+//! struct Doubler;
+//!
+//! impl Doubler {
+//!   #[memoize]
+//!   fn double(&self, n: u32) -> u32 {
+//!     n * 2
+//!   }
+//! }
+//! ```
+
+// lets the code generated by #[memoize] name this crate as
+// ::functional, like it does everywhere else, from inside it too
+extern crate self as functional;
+
+pub mod adaptors;
+pub mod cacher;
+pub mod executor;
+pub mod workout;
+
+pub use cacher::Cacher;
+pub use cacher::asynchronous::AsyncCacher;
+pub use cacher::fallible::{ErrorPolicy, TryCacher};
+pub use cacher::sync::SyncCacher;
+pub use memoize::memoize;
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::sync::atomic::{AtomicUsize, Ordering};
+  use std::sync::OnceLock;
+  use std::thread;
+  use std::time::Duration;
+
+  static FIB_CALLS: AtomicUsize = AtomicUsize::new(0);
+  static ADD_CALLS: AtomicUsize = AtomicUsize::new(0);
+  static SQUARE_CALLS: AtomicUsize = AtomicUsize::new(0);
+  static SLOW_CALLS: AtomicUsize = AtomicUsize::new(0);
+  static NEGATE_CALLS: AtomicUsize = AtomicUsize::new(0);
+  static HALVE_CALLS: AtomicUsize = AtomicUsize::new(0);
+
+  static CLOCK: OnceLock<cacher::clock::ManualClock> = OnceLock::new();
+
+  fn clock() -> cacher::clock::ManualClock {
+    CLOCK.get_or_init(cacher::clock::ManualClock::new).clone()
+  }
+
+  #[memoize]
+  fn fib(n: u64) -> u64 {
+    FIB_CALLS.fetch_add(1, Ordering::SeqCst);
+    match n {
+      0 | 1 => n,
+      _ => fib(n - 1) + fib(n - 2),
+    }
+  }
+
+  #[memoize(ttl = 3600)]
+  fn add(a: u32, mut b: u32) -> u32 {
+    ADD_CALLS.fetch_add(1, Ordering::SeqCst);
+    b += a;
+    b
+  }
+
+  #[memoize(capacity = 2)]
+  fn square(n: u32) -> String {
+    SQUARE_CALLS.fetch_add(1, Ordering::SeqCst);
+    (n * n).to_string()
+  }
+
+  #[memoize]
+  fn slow(n: u32) -> u32 {
+    SLOW_CALLS.fetch_add(1, Ordering::SeqCst);
+    thread::sleep(Duration::from_millis(50));
+    n
+  }
+
+  #[memoize(crate = crate)]
+  fn negate(n: i32) -> i32 {
+    NEGATE_CALLS.fetch_add(1, Ordering::SeqCst);
+    -n
+  }
+
+  #[memoize(ttl = 60, clock = clock())]
+  fn halve(n: u32) -> u32 {
+    HALVE_CALLS.fetch_add(1, Ordering::SeqCst);
+    n / 2
+  }
+
+  #[memoize]
+  fn forever(n: u32) -> u32 {
+    forever(n) + 1
+  }
+
+  #[test]
+  #[should_panic(expected = "recursive call for the same key")]
+  fn calling_itself_with_the_same_arguments_panics() {
+    forever(1);
+  }
+
+  #[test]
+  fn recursive_functions_calculate_each_value_once() {
+    assert_eq!(12586269025, fib(50));
+    assert_eq!(51, FIB_CALLS.load(Ordering::SeqCst));
+
+    assert_eq!(55, fib(10));
+    assert_eq!(51, FIB_CALLS.load(Ordering::SeqCst));
+  }
+
+  #[test]
+  fn many_arguments_are_keyed_on_the_tuple() {
+    assert_eq!(3, add(1, 2));
+    assert_eq!(3, add(1, 2));
+    assert_eq!(3, add(2, 1));
+
+    assert_eq!(2, ADD_CALLS.load(Ordering::SeqCst));
+  }
+
+  #[test]
+  fn capacity_bounds_the_cache() {
+    assert_eq!("4", square(2));
+    assert_eq!("9", square(3));
+    // 2 is the least recently used, so it goes
+    assert_eq!("16", square(4));
+    assert_eq!("9", square(3));
+    assert_eq!("4", square(2));
+
+    assert_eq!(4, SQUARE_CALLS.load(Ordering::SeqCst));
+  }
+
+  #[test]
+  fn threads_asking_at_once_calculate_once() {
+    let handles: Vec<_> = (0..8).map(|_| thread::spawn(|| slow(7))).collect();
+
+    for handle in handles {
+      assert_eq!(7, handle.join().unwrap());
+    }
+    assert_eq!(1, SLOW_CALLS.load(Ordering::SeqCst));
+  }
+
+  #[test]
+  fn the_crate_can_be_named() {
+    assert_eq!(-3, negate(3));
+    assert_eq!(-3, negate(3));
+    assert_eq!(1, NEGATE_CALLS.load(Ordering::SeqCst));
+  }
+
+  #[test]
+  fn results_expire_after_the_ttl() {
+    assert_eq!(5, halve(10));
+    clock().advance(Duration::from_secs(59));
+    assert_eq!(5, halve(10));
+    assert_eq!(1, HALVE_CALLS.load(Ordering::SeqCst));
+
+    clock().advance(Duration::from_secs(1));
+    assert_eq!(5, halve(10));
+    assert_eq!(2, HALVE_CALLS.load(Ordering::SeqCst));
+  }
+}
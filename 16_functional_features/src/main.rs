@@ -1,42 +1,9 @@
 // standard crates
 use std::thread;
 use std::time::Duration;
-use std::collections::HashMap;
-
-// Traits
-use std::hash::Hash;
-use std::clone::Clone;
-
-struct Cacher<T, A>
-  where T: Fn(A) -> u32,
-        A: Eq + Hash + Clone
-{
-  calculation: T,
-  value: HashMap<A, u32>
-}
 
-impl<T, A> Cacher<T, A>
-  where T: Fn(A) -> u32,
-        A: Eq + Hash + Clone
-{
-  fn new(calculation: T) -> Cacher<T, A> {
-    Cacher {
-      calculation,
-      value: HashMap::new()
-    }
-  }
-
-  fn value(&mut self, arg: A) -> u32 {
-    match self.value.get(&arg) {
-      Some(v) => *v,
-      None => {
-        let v = (self.calculation)(arg.clone());
-        self.value.insert(arg, v);
-        v
-      }
-    }
-  }
-}
+use functional::Cacher;
+use functional::workout::{Config, WorkoutPlanner};
 
 fn main() {
   let simulated_user_specified_value = 10;
@@ -56,6 +23,31 @@ fn main() {
     another_user_specified_value,
     another_simulated_random_number
   );
+
+  println!("\n======================\n");
+
+  plan_workouts();
+}
+
+// the same workouts, with rules read from a file instead of written in the code
+fn plan_workouts() {
+  // workout.toml is next to Cargo.toml, not in whatever directory this runs from
+  let path = concat!(env!("CARGO_MANIFEST_DIR"), "/workout.toml");
+  let config = Config::load(path).unwrap_or_else(|e| {
+    eprintln!("{}, using the default rules", e);
+    Config::default()
+  });
+
+  let mut planner = WorkoutPlanner::new(config);
+
+  let simulated_user_specified_value = 10;
+  let another_user_specified_value = 30;
+
+  println!("{}", planner.plan(simulated_user_specified_value));
+
+  println!("\n======================\n");
+
+  println!("{}", planner.plan(another_user_specified_value));
 }
 
 fn generate_workout_plan(intensity: u32, random_number: u32) {
@@ -0,0 +1,343 @@
+/*
+  ** The workout plan generator from the closures chapter, with its rules
+  ** taken out of the code. The thresholds and exercises come from a Config,
+  ** which can be read from a TOML or JSON file, and instead of printing the
+  ** planner returns a Plan that the caller can print, store or test.
+  **
+  ** Low intensities get every strength exercise, high intensities get one
+  ** cardio exercise, or now and then a rest day. The random choices come from
+  ** an Rng given to the planner, so a seeded or scripted one makes the plans
+  ** reproducible.
+*/
+
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+pub mod rng;
+
+use rng::{Rng, XorShift};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StrengthRule {
+  pub name: String,
+  pub reps_per_intensity: f64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CardioRule {
+  pub name: String,
+  pub minutes_per_intensity: f64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Config {
+  // plans below this intensity are strength workouts, the others are cardio
+  pub strength_below: u32,
+  // one in this many cardio days turns into a rest day, 1 makes every one of them a rest day
+  pub rest_day_one_in: u32,
+  #[serde(default)]
+  pub strength: Vec<StrengthRule>,
+  #[serde(default)]
+  pub cardio: Vec<CardioRule>,
+}
+
+// the rules generate_workout_plan used to hard-code
+impl Default for Config {
+  fn default() -> Config {
+    Config {
+      strength_below: 25,
+      rest_day_one_in: 10,
+      strength: vec![
+        StrengthRule { name: String::from("pushups"), reps_per_intensity: 1.0 },
+        StrengthRule { name: String::from("situps"), reps_per_intensity: 1.0 },
+      ],
+      cardio: vec![CardioRule { name: String::from("run"), minutes_per_intensity: 1.0 }],
+    }
+  }
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+  Io(io::Error),
+  Toml(toml::de::Error),
+  Json(serde_json::Error),
+  // the file isn't .toml or .json
+  UnknownFormat(String),
+  // the config parsed but its rules can't make a plan
+  Invalid(&'static str),
+}
+
+impl fmt::Display for ConfigError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      ConfigError::Io(e) => write!(f, "can't read workout config: {}", e),
+      ConfigError::Toml(e) => write!(f, "invalid workout config toml: {}", e),
+      ConfigError::Json(e) => write!(f, "invalid workout config json: {}", e),
+      ConfigError::UnknownFormat(path) => write!(f, "unknown workout config format: {}", path),
+      ConfigError::Invalid(reason) => write!(f, "invalid workout config: {}", reason),
+    }
+  }
+}
+
+impl Error for ConfigError {
+  fn source(&self) -> Option<&(dyn Error + 'static)> {
+    match self {
+      ConfigError::Io(e) => Some(e),
+      ConfigError::Toml(e) => Some(e),
+      ConfigError::Json(e) => Some(e),
+      _ => None,
+    }
+  }
+}
+
+impl From<io::Error> for ConfigError {
+  fn from(e: io::Error) -> ConfigError {
+    ConfigError::Io(e)
+  }
+}
+
+impl From<toml::de::Error> for ConfigError {
+  fn from(e: toml::de::Error) -> ConfigError {
+    ConfigError::Toml(e)
+  }
+}
+
+impl From<serde_json::Error> for ConfigError {
+  fn from(e: serde_json::Error) -> ConfigError {
+    ConfigError::Json(e)
+  }
+}
+
+impl Config {
+  pub fn from_toml(toml: &str) -> Result<Config, ConfigError> {
+    toml::from_str::<Config>(toml)?.validate()
+  }
+
+  pub fn from_json(json: &str) -> Result<Config, ConfigError> {
+    serde_json::from_str::<Config>(json)?.validate()
+  }
+
+  // the format is picked by the extension of the file
+  pub fn load<P: AsRef<Path>>(path: P) -> Result<Config, ConfigError> {
+    let path = path.as_ref();
+    let text = fs::read_to_string(path)?;
+
+    match path.extension().and_then(|ext| ext.to_str()) {
+      Some("toml") => Config::from_toml(&text),
+      Some("json") => Config::from_json(&text),
+      _ => Err(ConfigError::UnknownFormat(path.display().to_string())),
+    }
+  }
+
+  fn validate(self) -> Result<Config, ConfigError> {
+    if self.rest_day_one_in == 0 {
+      return Err(ConfigError::Invalid("rest_day_one_in has to be at least 1"));
+    }
+    if self.strength.is_empty() && self.strength_below > 0 {
+      return Err(ConfigError::Invalid("strength days need at least one strength exercise"));
+    }
+    if self.cardio.is_empty() {
+      return Err(ConfigError::Invalid("cardio days need at least one cardio exercise"));
+    }
+    // scale() casts to u32, which would quietly turn these into 0 or u32::MAX
+    if !self.strength.iter().all(|rule| valid_rate(rule.reps_per_intensity)) {
+      return Err(ConfigError::Invalid("reps_per_intensity has to be a finite number, at least 0"));
+    }
+    if !self.cardio.iter().all(|rule| valid_rate(rule.minutes_per_intensity)) {
+      return Err(ConfigError::Invalid("minutes_per_intensity has to be a finite number, at least 0"));
+    }
+
+    Ok(self)
+  }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Exercise {
+  pub name: String,
+  // strength exercises have reps, cardio ones last a while
+  pub reps: Option<u32>,
+  pub duration: Option<Duration>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Plan {
+  pub intensity: u32,
+  // empty on a rest day
+  pub exercises: Vec<Exercise>,
+  pub rest_day: bool,
+}
+
+// prints the plan the way generate_workout_plan used to
+impl fmt::Display for Plan {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    if self.rest_day {
+      return write!(f, "Take a break today! Remember to stay hydrated!");
+    }
+
+    for (i, exercise) in self.exercises.iter().enumerate() {
+      let when = if i == 0 { "Today" } else { "Next" };
+
+      if i > 0 {
+        writeln!(f)?;
+      }
+
+      match (exercise.reps, exercise.duration) {
+        (Some(reps), _) => write!(f, "{}, do {} {}!", when, reps, exercise.name)?,
+        (None, Some(duration)) => {
+          write!(f, "{}, {} for {} minutes!", when, exercise.name, duration.as_secs() / 60)?
+        },
+        (None, None) => write!(f, "{}, {}!", when, exercise.name)?,
+      }
+    }
+
+    Ok(())
+  }
+}
+
+pub struct WorkoutPlanner<G: Rng = XorShift> {
+  config: Config,
+  rng: G,
+}
+
+impl WorkoutPlanner {
+  // plans that change from one run to the next
+  pub fn new(config: Config) -> WorkoutPlanner {
+    WorkoutPlanner::with_rng(config, XorShift::from_time())
+  }
+}
+
+impl<G: Rng> WorkoutPlanner<G> {
+  pub fn with_rng(config: Config, rng: G) -> WorkoutPlanner<G> {
+    WorkoutPlanner { config, rng }
+  }
+
+  pub fn config(&self) -> &Config {
+    &self.config
+  }
+
+  pub fn plan(&mut self, intensity: u32) -> Plan {
+    let exercises = if intensity < self.config.strength_below {
+      self.config.strength.iter()
+        .map(|rule| Exercise {
+          name: rule.name.clone(),
+          reps: Some(scale(intensity, rule.reps_per_intensity)),
+          duration: None,
+        })
+        .collect()
+    } else if self.rng.below(self.config.rest_day_one_in) == 0 {
+      Vec::new()
+    } else {
+      let i = self.rng.below(self.config.cardio.len() as u32) as usize;
+      let rule = &self.config.cardio[i];
+
+      vec![Exercise {
+        name: rule.name.clone(),
+        reps: None,
+        duration: Some(Duration::from_secs(u64::from(scale(intensity, rule.minutes_per_intensity)) * 60)),
+      }]
+    };
+
+    Plan {
+      intensity,
+      rest_day: exercises.is_empty(),
+      exercises,
+    }
+  }
+}
+
+// negative rates give 0 and huge ones stop at u32::MAX
+fn valid_rate(rate: f64) -> bool {
+  rate.is_finite() && rate >= 0.0
+}
+
+fn scale(intensity: u32, rate: f64) -> u32 {
+  (f64::from(intensity) * rate).round() as u32
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  // always returns the same number
+  fn fixed(n: u32) -> impl FnMut() -> u32 {
+    move || n
+  }
+
+  #[test]
+  fn the_example_config_is_the_default() {
+    let config = Config::from_toml(include_str!("../workout.toml")).unwrap();
+
+    assert_eq!(Config::default(), config);
+  }
+
+  #[test]
+  fn low_intensity_is_strength() {
+    let mut planner = WorkoutPlanner::with_rng(Config::default(), fixed(0));
+    let plan = planner.plan(10);
+
+    assert!(!plan.rest_day);
+    assert_eq!(vec![Some(10), Some(10)], plan.exercises.iter().map(|e| e.reps).collect::<Vec<_>>());
+    assert_eq!("Today, do 10 pushups!\nNext, do 10 situps!", plan.to_string());
+  }
+
+  #[test]
+  fn high_intensity_is_cardio_or_rest() {
+    let mut rest = WorkoutPlanner::with_rng(Config::default(), fixed(0));
+    assert!(rest.plan(30).rest_day);
+    assert_eq!("Take a break today! Remember to stay hydrated!", rest.plan(30).to_string());
+
+    let mut run = WorkoutPlanner::with_rng(Config::default(), fixed(7));
+    let plan = run.plan(30);
+    assert_eq!(Some(Duration::from_secs(30 * 60)), plan.exercises[0].duration);
+    assert_eq!("Today, run for 30 minutes!", plan.to_string());
+  }
+
+  #[test]
+  fn seeded_plans_are_reproducible() {
+    let config = Config::from_json(r#"{
+      "strength_below": 0,
+      "rest_day_one_in": 3,
+      "cardio": [
+        { "name": "run", "minutes_per_intensity": 1.0 },
+        { "name": "swim", "minutes_per_intensity": 0.5 },
+        { "name": "bike", "minutes_per_intensity": 2.0 }
+      ]
+    }"#).unwrap();
+
+    let mut a = WorkoutPlanner::with_rng(config.clone(), XorShift::new(7));
+    let mut b = WorkoutPlanner::with_rng(config, XorShift::new(7));
+
+    let a: Vec<Plan> = (0..20).map(|i| a.plan(30 + i)).collect();
+    let b: Vec<Plan> = (0..20).map(|i| b.plan(30 + i)).collect();
+    assert_eq!(a, b);
+    assert!(a.iter().any(|plan| plan.rest_day));
+    assert!(a.iter().any(|plan| !plan.rest_day));
+  }
+
+  #[test]
+  fn invalid_configs() {
+    let no_cardio = "strength_below = 25\nrest_day_one_in = 10\n[[strength]]\nname = \"pushups\"\nreps_per_intensity = 1.0\n";
+    assert!(matches!(Config::from_toml(no_cardio), Err(ConfigError::Invalid(_))));
+
+    let cardio = "[[cardio]]\nname = \"run\"\nminutes_per_intensity = 1.0\n";
+    for reps in &["-1.0", "nan", "inf"] {
+      let bad_reps = format!("strength_below = 25\nrest_day_one_in = 10\n[[strength]]\nname = \"pushups\"\nreps_per_intensity = {}\n{}", reps, cardio);
+      assert!(matches!(Config::from_toml(&bad_reps), Err(ConfigError::Invalid(_))), "{}", reps);
+    }
+    let bad_minutes = r#"{ "strength_below": 0, "rest_day_one_in": 10, "cardio": [{ "name": "run", "minutes_per_intensity": -0.5 }] }"#;
+    assert!(matches!(Config::from_json(bad_minutes), Err(ConfigError::Invalid(_))));
+
+    let no_rest = r#"{ "strength_below": 0, "rest_day_one_in": 0, "cardio": [] }"#;
+    assert!(matches!(Config::from_json(no_rest), Err(ConfigError::Invalid(_))));
+
+    assert!(matches!(Config::from_json("{"), Err(ConfigError::Json(_))));
+    assert!(matches!(Config::load("README.md"), Err(ConfigError::UnknownFormat(_))));
+    assert!(matches!(Config::load("missing.toml"), Err(ConfigError::Io(_))));
+  }
+}
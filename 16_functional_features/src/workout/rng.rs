@@ -0,0 +1,90 @@
+/*
+  ** Where the planner gets its random numbers from. Anything that implements
+  ** Rng can be handed to it, including a closure, so tests can decide exactly
+  ** which numbers come out and get the same plan every time.
+*/
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub trait Rng {
+  fn next_u32(&mut self) -> u32;
+
+  // a number in 0..n, n has to be greater than 0
+  fn below(&mut self, n: u32) -> u32 {
+    self.next_u32() % n
+  }
+}
+
+// any closure that returns numbers is a source of random numbers
+impl<F: FnMut() -> u32> Rng for F {
+  fn next_u32(&mut self) -> u32 {
+    self()
+  }
+}
+
+// xorshift64*, not good enough for anything secret but plenty for
+// picking workouts, and the same seed always gives the same numbers
+#[derive(Debug, Clone)]
+pub struct XorShift {
+  state: u64,
+}
+
+impl XorShift {
+  pub fn new(seed: u64) -> XorShift {
+    // a state of 0 would only ever give 0s
+    XorShift { state: if seed == 0 { 0x9e37_79b9_7f4a_7c15 } else { seed } }
+  }
+
+  // a different seed every time, for when the plans don't have to be reproducible
+  pub fn from_time() -> XorShift {
+    let nanos = SystemTime::now()
+      .duration_since(UNIX_EPOCH)
+      .map(|d| d.as_nanos() as u64)
+      .unwrap_or(0);
+
+    XorShift::new(nanos)
+  }
+}
+
+impl Rng for XorShift {
+  fn next_u32(&mut self) -> u32 {
+    self.state ^= self.state >> 12;
+    self.state ^= self.state << 25;
+    self.state ^= self.state >> 27;
+    (self.state.wrapping_mul(0x2545_f491_4f6c_dd1d) >> 32) as u32
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn same_seed_same_numbers() {
+    let mut a = XorShift::new(42);
+    let mut b = XorShift::new(42);
+
+    let a: Vec<u32> = (0..5).map(|_| a.next_u32()).collect();
+    let b: Vec<u32> = (0..5).map(|_| b.next_u32()).collect();
+    assert_eq!(a, b);
+  }
+
+  #[test]
+  fn zero_seed_still_varies() {
+    let mut rng = XorShift::new(0);
+
+    assert_ne!(rng.next_u32(), rng.next_u32());
+  }
+
+  #[test]
+  fn closures_are_rngs() {
+    let mut n = 0;
+    let mut rng = || {
+      n += 7;
+      n
+    };
+
+    assert_eq!(7, rng.below(10));
+    assert_eq!(4, rng.below(10));
+  }
+}
@@ -0,0 +1,274 @@
+/*
+  ** #[memoize] turns a free function into one that remembers its results,
+  ** using a functional::Cacher keyed on its arguments:
+  **
+  **   #[memoize(capacity = 100, ttl = 60)]
+  **   fn workout(intensity: u32, random_number: u32) -> Plan { ... }
+  **
+  ** A function with a single argument is keyed on it, one with more
+  ** arguments is keyed on the tuple of all of them, so the arguments have to
+  ** be Eq + Hash + Clone and the return type has to be Clone.
+  **
+  ** Options:
+  **   capacity = N     keeps at most N results, forgetting the least recently used
+  **   ttl = N          results expire N seconds after they were calculated
+  **   crate = path     where the functional crate is, when it isn't ::functional,
+  **                    e.g. because it was renamed in Cargo.toml
+  **   clock = expr     where the time for the ttl comes from, e.g. a ManualClock
+  **                    in tests, the expression runs on the first call
+  **
+  ** The cacher is a static shared by every thread, behind a Mutex. The lock is
+  ** not held while the body runs, so a memoized function can call itself
+  ** (the Fibonacci numbers are the classic example), and when two threads ask
+  ** for the same arguments at once only one of them runs the body, the other
+  ** one waits for its result.
+*/
+
+extern crate proc_macro;
+
+use proc_macro::TokenStream;
+use proc_macro2::Span;
+use quote::{format_ident, quote};
+use syn::punctuated::Punctuated;
+use syn::{parse_macro_input, Error, Expr, FnArg, ItemFn, Lit, LitInt, MetaNameValue, Path, ReturnType, Token};
+
+struct Options {
+  capacity: Option<usize>,
+  ttl: Option<u64>,
+  krate: Path,
+  clock: Option<Expr>,
+}
+
+impl Default for Options {
+  fn default() -> Options {
+    Options {
+      capacity: None,
+      ttl: None,
+      krate: syn::parse_quote!(::functional),
+      clock: None,
+    }
+  }
+}
+
+#[proc_macro_attribute]
+pub fn memoize(attr: TokenStream, item: TokenStream) -> TokenStream {
+  let options = parse_macro_input!(attr with Punctuated::<MetaNameValue, Token![,]>::parse_terminated);
+  let function = parse_macro_input!(item as ItemFn);
+
+  match parse_options(options).and_then(|options| expand(options, function)) {
+    Ok(tokens) => tokens.into(),
+    Err(e) => e.to_compile_error().into(),
+  }
+}
+
+fn parse_options(options: Punctuated<MetaNameValue, Token![,]>) -> syn::Result<Options> {
+  let mut parsed = Options::default();
+
+  for option in options {
+    if option.path.is_ident("capacity") {
+      let capacity = whole_number(&option.value)?.base10_parse()?;
+      if capacity == 0 {
+        return Err(Error::new_spanned(&option.value, "the capacity has to be at least 1"));
+      }
+      parsed.capacity = Some(capacity);
+    } else if option.path.is_ident("ttl") {
+      parsed.ttl = Some(whole_number(&option.value)?.base10_parse()?);
+    } else if option.path.is_ident("crate") {
+      parsed.krate = match &option.value {
+        Expr::Path(expr) if expr.qself.is_none() => expr.path.clone(),
+        _ => return Err(Error::new_spanned(&option.value, "expected a path to the functional crate")),
+      };
+    } else if option.path.is_ident("clock") {
+      parsed.clock = Some(option.value);
+    } else {
+      return Err(Error::new_spanned(&option.path, "unknown option, expected `capacity`, `ttl`, `crate` or `clock`"));
+    }
+  }
+
+  Ok(parsed)
+}
+
+fn whole_number(value: &Expr) -> syn::Result<&LitInt> {
+  match value {
+    Expr::Lit(expr) => match &expr.lit {
+      Lit::Int(int) => Ok(int),
+      _ => Err(Error::new_spanned(value, "expected a whole number")),
+    },
+    _ => Err(Error::new_spanned(value, "expected a whole number")),
+  }
+}
+
+/*
+  ** The body moves to an inner function with the original signature, and
+  ** the outer function takes its arguments as __arg0, __arg1, ... so patterns
+  ** like `mut x` or `(a, b)` keep working in the body.
+*/
+fn expand(options: Options, function: ItemFn) -> syn::Result<proc_macro2::TokenStream> {
+  let sig = &function.sig;
+
+  if !sig.generics.params.is_empty() {
+    return Err(Error::new_spanned(&sig.generics, "memoize can't be used on generic functions"));
+  }
+  if let Some(constness) = &sig.constness {
+    return Err(Error::new_spanned(constness, "memoize can't be used on const functions"));
+  }
+  if let Some(asyncness) = &sig.asyncness {
+    return Err(Error::new_spanned(asyncness, "memoize can't be used on async functions"));
+  }
+
+  let ret = match &sig.output {
+    ReturnType::Type(_, ty) => ty,
+    ReturnType::Default => {
+      return Err(Error::new_spanned(sig, "memoize needs a function that returns something"));
+    },
+  };
+
+  let mut types = Vec::new();
+  for input in &sig.inputs {
+    match input {
+      FnArg::Typed(arg) => types.push(&arg.ty),
+      FnArg::Receiver(receiver) => {
+        return Err(Error::new_spanned(receiver, "memoize can only be used on free functions"));
+      },
+    }
+  }
+
+  let args: Vec<_> = (0..types.len()).map(|i| format_ident!("__arg{}", i)).collect();
+  let inner = syn::Ident::new("__memoized", Span::call_site());
+
+  // a single argument is its own key
+  let (key_type, key, calculation) = if types.len() == 1 {
+    let (ty, arg) = (&types[0], &args[0]);
+    (quote!(#ty), quote!(#arg), quote!(#inner))
+  } else {
+    (
+      quote!((#(#types,)*)),
+      quote!((#(#args,)*)),
+      quote!((|(#(#args,)*): (#(#types,)*)| #inner(#(#args),*))),
+    )
+  };
+
+  let krate = &options.krate;
+  let mut cacher = match options.capacity {
+    Some(capacity) => quote!(#krate::Cacher::with_capacity(#capacity, #calculation as fn(#key_type) -> #ret)),
+    None => quote!(#krate::Cacher::new(#calculation as fn(#key_type) -> #ret)),
+  };
+  if let Some(ttl) = options.ttl {
+    cacher = quote!(#cacher.with_ttl(::std::time::Duration::from_secs(#ttl)));
+  }
+
+  // the type of the static has to be written out, so a clock given
+  // as an expression is boxed instead of having its type named
+  let clock_type = match &options.clock {
+    Some(clock) => {
+      let clock_type = quote!(::std::boxed::Box<dyn #krate::cacher::clock::Clock + ::std::marker::Send>);
+      cacher = quote!(#cacher.with_clock(::std::boxed::Box::new(#clock) as #clock_type));
+      clock_type
+    },
+    None => quote!(#krate::cacher::clock::SystemClock),
+  };
+
+  let attrs = &function.attrs;
+  let vis = &function.vis;
+  let block = &function.block;
+
+  let mut inner_sig = sig.clone();
+  inner_sig.ident = inner.clone();
+
+  let mut outer_sig = sig.clone();
+  for (input, arg) in outer_sig.inputs.iter_mut().zip(&args) {
+    if let FnArg::Typed(typed) = input {
+      *typed.pat = syn::parse_quote!(#arg);
+    }
+  }
+
+  Ok(quote! {
+    #(#attrs)*
+    #vis #outer_sig {
+      #inner_sig #block
+
+      static MEMO: ::std::sync::OnceLock<
+        #krate::cacher::memo::Memo<#key_type, #ret, #clock_type>
+      > = ::std::sync::OnceLock::new();
+
+      MEMO.get_or_init(|| #krate::cacher::memo::Memo::new(#cacher)).value(#key)
+    }
+  })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use syn::parse::Parser;
+
+  // what #[memoize(attr)] on item expands to, or the error it gives
+  fn memoize(attr: proc_macro2::TokenStream, item: proc_macro2::TokenStream) -> syn::Result<String> {
+    let options = Punctuated::<MetaNameValue, Token![,]>::parse_terminated.parse2(attr)?;
+    let function = syn::parse2(item)?;
+
+    parse_options(options)
+      .and_then(|options| expand(options, function))
+      .map(|tokens| tokens.to_string())
+  }
+
+  fn error(attr: proc_macro2::TokenStream, item: proc_macro2::TokenStream) -> String {
+    memoize(attr, item).unwrap_err().to_string()
+  }
+
+  #[test]
+  fn expands_to_a_memo() {
+    let expanded = memoize(quote!(capacity = 2, ttl = 60), quote!(fn add(a: u32, b: u32) -> u32 { a + b })).unwrap();
+
+    assert!(expanded.contains(":: functional :: cacher :: memo :: Memo < (u32 , u32 ,) , u32 , :: functional :: cacher :: clock :: SystemClock >"));
+    assert!(expanded.contains("with_capacity (2usize"));
+    assert!(expanded.contains("from_secs (60u64)"));
+  }
+
+  #[test]
+  fn options_name_the_crate_and_the_clock() {
+    let expanded = memoize(quote!(crate = my::functional, clock = clock()), quote!(fn f(n: u32) -> u32 { n })).unwrap();
+
+    assert!(expanded.contains("my :: functional :: Cacher :: new"));
+    assert!(expanded.contains("with_clock (:: std :: boxed :: Box :: new (clock ())"));
+    // every path into the crate starts with the one that was given
+    assert_eq!(expanded.matches("my :: functional").count(), expanded.matches("functional").count());
+  }
+
+  #[test]
+  fn bad_options_are_rejected() {
+    let f = quote!(fn f(n: u32) -> u32 { n });
+
+    assert_eq!("the capacity has to be at least 1", error(quote!(capacity = 0), f.clone()));
+    assert_eq!("expected a whole number", error(quote!(capacity = "10"), f.clone()));
+    assert_eq!("expected a whole number", error(quote!(ttl = 1.5), f.clone()));
+    assert_eq!("expected a path to the functional crate", error(quote!(crate = 1), f.clone()));
+    assert_eq!(
+      "unknown option, expected `capacity`, `ttl`, `crate` or `clock`",
+      error(quote!(size = 10), f)
+    );
+  }
+
+  #[test]
+  fn functions_that_cant_be_memoized_are_rejected() {
+    assert_eq!(
+      "memoize can't be used on generic functions",
+      error(quote!(), quote!(fn f<T>(n: T) -> T { n }))
+    );
+    assert_eq!(
+      "memoize can't be used on const functions",
+      error(quote!(), quote!(const fn f(n: u32) -> u32 { n }))
+    );
+    assert_eq!(
+      "memoize can't be used on async functions",
+      error(quote!(), quote!(async fn f(n: u32) -> u32 { n }))
+    );
+    assert_eq!(
+      "memoize can only be used on free functions",
+      error(quote!(), quote!(fn f(&self, n: u32) -> u32 { n }))
+    );
+    assert_eq!(
+      "memoize needs a function that returns something",
+      error(quote!(), quote!(fn f(n: u32) { println!("{}", n) }))
+    );
+  }
+}
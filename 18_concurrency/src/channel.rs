@@ -0,0 +1,511 @@
+/*
+  ** A channel that holds at most `capacity` messages. When it's full,
+  ** send blocks until a receiver makes room, so a fast producer is slowed
+  ** down to the pace of its consumers instead of filling up the memory
+  ** (this is called backpressure).
+  **
+  ** Unlike mpsc, both ends can be cloned: many threads can send and many
+  ** threads can receive (multiple producer, multiple consumer), and each
+  ** message is received by only one of them.
+  **
+  ** The messages live in a VecDeque behind a Mutex. Two Condvars let the
+  ** threads sleep until something changes: receivers wait on not_empty,
+  ** senders wait on not_full.
+  **
+  ** Disconnecting works like mpsc and returns the same errors: once every
+  ** Receiver is dropped sending fails and gives the message back, and once
+  ** every Sender is dropped receiving fails, after the messages that were
+  ** already sent have been received.
+*/
+
+use std::collections::VecDeque;
+use std::error::Error;
+use std::fmt;
+use std::sync::mpsc::{RecvError, RecvTimeoutError, SendError, TryRecvError, TrySendError};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use crate::select::Signal;
+
+// mpsc has no stable error for send_timeout yet, so this one is ours
+#[derive(PartialEq, Eq, Clone, Copy)]
+pub enum SendTimeoutError<T> {
+  // the channel stayed full for the whole timeout
+  Timeout(T),
+  Disconnected(T),
+}
+
+// like the mpsc errors, the message isn't printed so T doesn't have to be Debug
+impl<T> fmt::Debug for SendTimeoutError<T> {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      SendTimeoutError::Timeout(_) => write!(f, "Timeout(..)"),
+      SendTimeoutError::Disconnected(_) => write!(f, "Disconnected(..)"),
+    }
+  }
+}
+
+impl<T> fmt::Display for SendTimeoutError<T> {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      SendTimeoutError::Timeout(_) => write!(f, "timed out waiting on send operation"),
+      SendTimeoutError::Disconnected(_) => write!(f, "sending on a disconnected channel"),
+    }
+  }
+}
+
+impl<T> Error for SendTimeoutError<T> {}
+
+struct State<T> {
+  queue: VecDeque<T>,
+  senders: usize,
+  receivers: usize,
+  // the Selects waiting on this channel, they can't wait on
+  // not_empty since they are waiting on other channels too
+  watchers: Vec<Arc<Signal>>,
+}
+
+impl<T> State<T> {
+  fn push(&mut self, value: T) {
+    self.queue.push_back(value);
+    self.notify_watchers();
+  }
+
+  fn notify_watchers(&self) {
+    for signal in &self.watchers {
+      signal.notify();
+    }
+  }
+}
+
+struct Channel<T> {
+  state: Mutex<State<T>>,
+  capacity: usize,
+  not_empty: Condvar,
+  not_full: Condvar,
+}
+
+impl<T> Channel<T> {
+  fn lock(&self) -> MutexGuard<'_, State<T>> {
+    // nothing panics while holding the lock, but if a message's
+    // drop did, the queue itself is still in a usable state
+    self.state.lock().unwrap_or_else(|e| e.into_inner())
+  }
+}
+
+// panics when capacity is 0, there has to be room for at least one message
+pub fn bounded<T>(capacity: usize) -> (Sender<T>, Receiver<T>) {
+  assert!(capacity > 0, "a bounded channel needs room for at least one message");
+
+  let channel = Arc::new(Channel {
+    state: Mutex::new(State {
+      queue: VecDeque::with_capacity(capacity),
+      senders: 1,
+      receivers: 1,
+      watchers: Vec::new(),
+    }),
+    capacity,
+    not_empty: Condvar::new(),
+    not_full: Condvar::new(),
+  });
+
+  (Sender { channel: Arc::clone(&channel) }, Receiver { channel })
+}
+
+// None means no deadline, for a timeout too big to be added to now
+fn deadline(timeout: Duration) -> Option<Instant> {
+  Instant::now().checked_add(timeout)
+}
+
+pub struct Sender<T> {
+  channel: Arc<Channel<T>>,
+}
+
+impl<T> Sender<T> {
+  // blocks while the channel is full
+  pub fn send(&self, value: T) -> Result<(), SendError<T>> {
+    self.send_until(value, None).map_err(|e| match e {
+      SendTimeoutError::Disconnected(v) | SendTimeoutError::Timeout(v) => SendError(v),
+    })
+  }
+
+  // fails right away with Full instead of blocking
+  pub fn try_send(&self, value: T) -> Result<(), TrySendError<T>> {
+    let mut state = self.channel.lock();
+
+    if state.receivers == 0 {
+      return Err(TrySendError::Disconnected(value));
+    }
+    if state.queue.len() == self.channel.capacity {
+      return Err(TrySendError::Full(value));
+    }
+
+    state.push(value);
+    self.channel.not_empty.notify_one();
+    Ok(())
+  }
+
+  // blocks while the channel is full, for at most timeout
+  pub fn send_timeout(&self, value: T, timeout: Duration) -> Result<(), SendTimeoutError<T>> {
+    self.send_until(value, deadline(timeout))
+  }
+
+  pub fn capacity(&self) -> usize {
+    self.channel.capacity
+  }
+
+  fn send_until(&self, value: T, deadline: Option<Instant>) -> Result<(), SendTimeoutError<T>> {
+    let mut state = self.channel.lock();
+
+    loop {
+      if state.receivers == 0 {
+        return Err(SendTimeoutError::Disconnected(value));
+      }
+      if state.queue.len() < self.channel.capacity {
+        break;
+      }
+
+      state = match deadline {
+        None => self.channel.not_full.wait(state).unwrap_or_else(|e| e.into_inner()),
+        Some(deadline) => {
+          let now = Instant::now();
+          if now >= deadline {
+            return Err(SendTimeoutError::Timeout(value));
+          }
+
+          self.channel.not_full
+            .wait_timeout(state, deadline - now)
+            .unwrap_or_else(|e| e.into_inner())
+            .0
+        },
+      };
+    }
+
+    state.push(value);
+    self.channel.not_empty.notify_one();
+    Ok(())
+  }
+}
+
+impl<T> Clone for Sender<T> {
+  fn clone(&self) -> Sender<T> {
+    self.channel.lock().senders += 1;
+    Sender { channel: Arc::clone(&self.channel) }
+  }
+}
+
+impl<T> Drop for Sender<T> {
+  fn drop(&mut self) {
+    let mut state = self.channel.lock();
+    state.senders -= 1;
+
+    // the receivers waiting for a message that will never come
+    if state.senders == 0 {
+      self.channel.not_empty.notify_all();
+      state.notify_watchers();
+    }
+  }
+}
+
+pub struct Receiver<T> {
+  channel: Arc<Channel<T>>,
+}
+
+impl<T> Receiver<T> {
+  // blocks while the channel is empty
+  pub fn recv(&self) -> Result<T, RecvError> {
+    self.recv_until(None).map_err(|_| RecvError)
+  }
+
+  pub fn try_recv(&self) -> Result<T, TryRecvError> {
+    let mut state = self.channel.lock();
+
+    match state.queue.pop_front() {
+      Some(value) => {
+        self.channel.not_full.notify_one();
+        Ok(value)
+      },
+      None if state.senders == 0 => Err(TryRecvError::Disconnected),
+      None => Err(TryRecvError::Empty),
+    }
+  }
+
+  // blocks while the channel is empty, for at most timeout
+  pub fn recv_timeout(&self, timeout: Duration) -> Result<T, RecvTimeoutError> {
+    self.recv_until(deadline(timeout))
+  }
+
+  // blocks waiting for messages until every Sender is dropped
+  pub fn iter(&self) -> Iter<'_, T> {
+    Iter { rx: self }
+  }
+
+  pub fn try_iter(&self) -> TryIter<'_, T> {
+    TryIter { rx: self }
+  }
+
+  // the signal is notified when a message is sent or every Sender is dropped
+  pub(crate) fn watch(&self, signal: &Arc<Signal>) {
+    self.channel.lock().watchers.push(Arc::clone(signal));
+  }
+
+  pub(crate) fn unwatch(&self, signal: &Arc<Signal>) {
+    self.channel.lock().watchers.retain(|s| !Arc::ptr_eq(s, signal));
+  }
+
+  fn recv_until(&self, deadline: Option<Instant>) -> Result<T, RecvTimeoutError> {
+    let mut state = self.channel.lock();
+
+    loop {
+      if let Some(value) = state.queue.pop_front() {
+        self.channel.not_full.notify_one();
+        return Ok(value);
+      }
+      if state.senders == 0 {
+        return Err(RecvTimeoutError::Disconnected);
+      }
+
+      state = match deadline {
+        None => self.channel.not_empty.wait(state).unwrap_or_else(|e| e.into_inner()),
+        Some(deadline) => {
+          let now = Instant::now();
+          if now >= deadline {
+            return Err(RecvTimeoutError::Timeout);
+          }
+
+          self.channel.not_empty
+            .wait_timeout(state, deadline - now)
+            .unwrap_or_else(|e| e.into_inner())
+            .0
+        },
+      };
+    }
+  }
+}
+
+impl<T> Clone for Receiver<T> {
+  fn clone(&self) -> Receiver<T> {
+    self.channel.lock().receivers += 1;
+    Receiver { channel: Arc::clone(&self.channel) }
+  }
+}
+
+impl<T> Drop for Receiver<T> {
+  fn drop(&mut self) {
+    let mut state = self.channel.lock();
+    state.receivers -= 1;
+
+    // the senders waiting for room that will never be made
+    if state.receivers == 0 {
+      self.channel.not_full.notify_all();
+    }
+  }
+}
+
+pub struct Iter<'a, T> {
+  rx: &'a Receiver<T>,
+}
+
+impl<'a, T> Iterator for Iter<'a, T> {
+  type Item = T;
+
+  fn next(&mut self) -> Option<T> {
+    self.rx.recv().ok()
+  }
+}
+
+// only the messages that are already there, without blocking
+pub struct TryIter<'a, T> {
+  rx: &'a Receiver<T>,
+}
+
+impl<'a, T> Iterator for TryIter<'a, T> {
+  type Item = T;
+
+  fn next(&mut self) -> Option<T> {
+    self.rx.try_recv().ok()
+  }
+}
+
+pub struct IntoIter<T> {
+  rx: Receiver<T>,
+}
+
+impl<T> Iterator for IntoIter<T> {
+  type Item = T;
+
+  fn next(&mut self) -> Option<T> {
+    self.rx.recv().ok()
+  }
+}
+
+// so a Receiver can be used in a for loop, like rx in channels_iterator
+impl<T> IntoIterator for Receiver<T> {
+  type Item = T;
+  type IntoIter = IntoIter<T>;
+
+  fn into_iter(self) -> IntoIter<T> {
+    IntoIter { rx: self }
+  }
+}
+
+impl<'a, T> IntoIterator for &'a Receiver<T> {
+  type Item = T;
+  type IntoIter = Iter<'a, T>;
+
+  fn into_iter(self) -> Iter<'a, T> {
+    self.iter()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::sync::{mpsc, Barrier};
+  use std::thread;
+
+  #[test]
+  fn full_channel_refuses_try_send() {
+    let (tx, rx) = bounded(2);
+
+    assert_eq!(Ok(()), tx.try_send(1));
+    assert_eq!(Ok(()), tx.try_send(2));
+    assert_eq!(Err(TrySendError::Full(3)), tx.try_send(3));
+
+    assert_eq!(Ok(1), rx.recv());
+    assert_eq!(Ok(()), tx.try_send(3));
+    assert_eq!(vec![2, 3], rx.try_iter().collect::<Vec<_>>());
+  }
+
+  #[test]
+  fn send_blocks_until_there_is_room() {
+    let (tx, rx) = bounded(1);
+    // the producer tells when each send went through
+    let (sent_tx, sent) = mpsc::channel();
+
+    let producer = thread::spawn(move || {
+      for i in 0..5 {
+        tx.send(i).unwrap();
+        sent_tx.send(i).unwrap();
+      }
+    });
+
+    for i in 0..5 {
+      // with i in the channel it's full, so the producer
+      // can't get the next one through until i is received
+      assert_eq!(Ok(i), sent.recv());
+      assert!(sent.try_recv().is_err());
+      assert_eq!(1, rx.channel.lock().queue.len());
+      assert_eq!(Ok(i), rx.recv());
+    }
+
+    producer.join().unwrap();
+  }
+
+  #[test]
+  fn timeouts() {
+    let (tx, rx) = bounded(1);
+
+    assert_eq!(Err(RecvTimeoutError::Timeout), rx.recv_timeout(Duration::from_millis(10)));
+
+    tx.send(1).unwrap();
+    assert_eq!(Err(SendTimeoutError::Timeout(2)), tx.send_timeout(2, Duration::from_millis(10)));
+
+    assert_eq!(Ok(1), rx.recv_timeout(Duration::from_millis(10)));
+    assert_eq!(Ok(()), tx.send_timeout(2, Duration::MAX));
+  }
+
+  #[test]
+  fn dropping_every_sender_disconnects_after_the_queue() {
+    let (tx, rx) = bounded(4);
+    let tx2 = tx.clone();
+
+    tx.send("hi").unwrap();
+    drop(tx);
+    // tx2 could still send something
+    assert_eq!(Ok("hi"), rx.try_recv());
+    assert_eq!(Err(TryRecvError::Empty), rx.try_recv());
+
+    // the message that was sent is still received
+    tx2.send("there").unwrap();
+    drop(tx2);
+    assert_eq!(Ok("there"), rx.recv());
+    assert_eq!(Err(RecvError), rx.recv());
+    assert_eq!(Err(TryRecvError::Disconnected), rx.try_recv());
+    assert_eq!(Err(RecvTimeoutError::Disconnected), rx.recv_timeout(Duration::from_secs(1)));
+  }
+
+  #[test]
+  fn dropping_every_receiver_wakes_blocked_senders() {
+    let (tx, rx) = bounded(1);
+    tx.send(1).unwrap();
+    let barrier = Arc::new(Barrier::new(2));
+
+    let blocked = {
+      let barrier = Arc::clone(&barrier);
+      thread::spawn(move || {
+        barrier.wait();
+        tx.send(2)
+      })
+    };
+
+    // the channel is full, so the send waits for room, and if rx is
+    // dropped before it even starts waiting it fails all the same
+    barrier.wait();
+    drop(rx);
+
+    assert_eq!(Err(SendError(2)), blocked.join().unwrap());
+  }
+
+  #[test]
+  fn many_producers_many_consumers() {
+    let (tx, rx) = bounded(3);
+    let mut producers = vec![];
+    let mut consumers = vec![];
+
+    for p in 0..4 {
+      let tx = tx.clone();
+      producers.push(thread::spawn(move || {
+        for i in 0..250 {
+          tx.send(p * 1000 + i).unwrap();
+        }
+      }));
+    }
+    drop(tx);
+
+    for _ in 0..4 {
+      let rx = rx.clone();
+      consumers.push(thread::spawn(move || rx.into_iter().collect::<Vec<_>>()));
+    }
+    drop(rx);
+
+    for producer in producers {
+      producer.join().unwrap();
+    }
+
+    let mut received: Vec<_> = consumers.into_iter().flat_map(|c| c.join().unwrap()).collect();
+    received.sort_unstable();
+
+    let mut sent: Vec<_> = (0..4).flat_map(|p| (0..250).map(move |i| p * 1000 + i)).collect();
+    sent.sort_unstable();
+    assert_eq!(sent, received);
+  }
+
+  #[test]
+  fn a_select_leaves_no_watchers_behind() {
+    let (_tx, rx) = bounded::<u32>(1);
+
+    crate::select::Select::new()
+      .recv(&rx, |_| ())
+      .timeout(Duration::from_millis(1), || ())
+      .wait();
+
+    assert!(rx.channel.lock().watchers.is_empty());
+  }
+
+  #[test]
+  #[should_panic]
+  fn zero_capacity_panics() {
+    bounded::<u32>(0);
+  }
+}
@@ -0,0 +1,525 @@
+/*
+  ** A Mutex that catches lock order inversions in debug builds. If one
+  ** thread locks a and then b while holding a, and another locks b and then
+  ** a, each can end up holding the lock the other one is waiting for, and
+  ** both wait forever. Whether that happens depends on timing, so it can
+  ** pass every test and still hang once in a while.
+  **
+  ** So every DebugMutex remembers the order the mutexes were locked in, by
+  ** any thread, and when a lock would go against an order seen before it
+  ** panics (or only logs, see set_on_inversion) before it even tries to
+  ** lock, with the backtraces of both times the mutexes were locked:
+  **
+  **   let a = DebugMutex::new(1);
+  **   let b = DebugMutex::new(2);
+  **
+  **   {
+  **     let _a = a.lock().unwrap();
+  **     let _b = b.lock().unwrap(); // a then b, remembered
+  **   }
+  **
+  **   let _b = b.lock().unwrap();
+  **   let _a = a.lock().unwrap(); // b then a, panics
+  **
+  ** Longer cycles are caught too, like a then b, b then c, and c then a.
+  **
+  ** All that bookkeeping, and capturing a backtrace on every lock, is
+  ** slow, so it's only done in debug builds. In release builds a DebugMutex
+  ** is just a Mutex and its guard a MutexGuard, they have the same methods
+  ** and the code using them doesn't change.
+  **
+  ** try_lock gives up instead of waiting, so it can't deadlock and doesn't
+  ** add to the order. The mutexes locked while holding its lock still do.
+*/
+
+#[cfg(debug_assertions)]
+mod graph;
+
+#[cfg(debug_assertions)]
+pub use self::checked::{DebugGuard, DebugMutex};
+
+#[cfg(not(debug_assertions))]
+pub type DebugMutex<T> = std::sync::Mutex<T>;
+
+#[cfg(not(debug_assertions))]
+pub type DebugGuard<'a, T> = std::sync::MutexGuard<'a, T>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OnInversion {
+  Panic,
+  // prints the report to stderr and locks anyway, which
+  // hangs for good if the thread is already holding that mutex
+  Log,
+}
+
+// panicking is the default, it's for every DebugMutex of the program
+// does nothing in release builds, where nothing is checked
+pub fn set_on_inversion(on_inversion: OnInversion) {
+  #[cfg(debug_assertions)]
+  checked::PANIC.store(on_inversion == OnInversion::Panic, std::sync::atomic::Ordering::SeqCst);
+
+  #[cfg(not(debug_assertions))]
+  let _ = on_inversion;
+}
+
+#[cfg(debug_assertions)]
+mod checked {
+  use std::backtrace::Backtrace;
+  use std::cell::RefCell;
+  use std::collections::HashMap;
+  use std::fmt::{self, Write};
+  use std::ops::{Deref, DerefMut};
+  use std::panic::Location;
+  use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+  use std::sync::{Arc, LockResult, Mutex, MutexGuard, OnceLock, PoisonError, TryLockError, TryLockResult};
+
+  use super::graph::Graph;
+
+  pub(super) static PANIC: AtomicBool = AtomicBool::new(true);
+
+  static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+
+  // an edge from a to b of the lock order graph
+  struct Edge {
+    // where a was locked
+    held: Arc<Backtrace>,
+    // where b was locked while holding a
+    locked: Arc<Backtrace>,
+  }
+
+  struct Registry {
+    order: Graph<Edge>,
+    // where each mutex was created, to tell them apart in the reports
+    created: HashMap<usize, &'static Location<'static>>,
+  }
+
+  // shared by every thread, it's only locked for the bookkeeping
+  fn registry() -> MutexGuard<'static, Registry> {
+    static REGISTRY: OnceLock<Mutex<Registry>> = OnceLock::new();
+
+    REGISTRY
+      .get_or_init(|| Mutex::new(Registry { order: Graph::new(), created: HashMap::new() }))
+      .lock()
+      // a panicking report leaves nothing half done
+      .unwrap_or_else(PoisonError::into_inner)
+  }
+
+  struct Held {
+    id: usize,
+    locked: Arc<Backtrace>,
+  }
+
+  thread_local! {
+    // the DebugMutexes the current thread is holding, in the order it locked them
+    static HELD: RefCell<Vec<Held>> = const { RefCell::new(Vec::new()) };
+  }
+
+  // takes the mutex out of the registry when it's dropped, a field of its own
+  // so DebugMutex doesn't need Drop and into_inner can move the Mutex out
+  struct Id(usize);
+
+  impl Drop for Id {
+    fn drop(&mut self) {
+      let mut registry = registry();
+
+      registry.order.remove(self.0);
+      registry.created.remove(&self.0);
+    }
+  }
+
+  pub struct DebugMutex<T> {
+    id: Id,
+    inner: Mutex<T>,
+  }
+
+  impl<T> DebugMutex<T> {
+    #[track_caller]
+    pub fn new(value: T) -> DebugMutex<T> {
+      let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+      registry().created.insert(id, Location::caller());
+
+      DebugMutex {
+        id: Id(id),
+        inner: Mutex::new(value),
+      }
+    }
+
+    // the same as Mutex::lock, after checking the lock order
+    pub fn lock(&self) -> LockResult<DebugGuard<'_, T>> {
+      let locked = Arc::new(Backtrace::force_capture());
+      self.check_order(&locked);
+
+      match self.inner.lock() {
+        Ok(guard) => Ok(self.guard(guard, locked)),
+        Err(err) => Err(PoisonError::new(self.guard(err.into_inner(), locked))),
+      }
+    }
+
+    pub fn try_lock(&self) -> TryLockResult<DebugGuard<'_, T>> {
+      let guard = match self.inner.try_lock() {
+        Ok(guard) => Ok(guard),
+        Err(TryLockError::Poisoned(err)) => Err(err.into_inner()),
+        Err(TryLockError::WouldBlock) => return Err(TryLockError::WouldBlock),
+      };
+
+      let locked = Arc::new(Backtrace::force_capture());
+
+      match guard {
+        Ok(guard) => Ok(self.guard(guard, locked)),
+        Err(guard) => Err(TryLockError::Poisoned(PoisonError::new(self.guard(guard, locked)))),
+      }
+    }
+
+    pub fn is_poisoned(&self) -> bool {
+      self.inner.is_poisoned()
+    }
+
+    pub fn into_inner(self) -> LockResult<T> {
+      self.inner.into_inner()
+    }
+
+    pub fn get_mut(&mut self) -> LockResult<&mut T> {
+      self.inner.get_mut()
+    }
+
+    fn guard<'a>(&'a self, guard: MutexGuard<'a, T>, locked: Arc<Backtrace>) -> DebugGuard<'a, T> {
+      HELD.with(|held| held.borrow_mut().push(Held { id: self.id.0, locked }));
+
+      DebugGuard { guard, id: self.id.0 }
+    }
+
+    // adds an edge from every mutex the thread is holding to this one,
+    // unless it goes against the order seen so far
+    fn check_order(&self, locked: &Arc<Backtrace>) {
+      let id = self.id.0;
+      let mut reports = Vec::new();
+
+      HELD.with(|held| {
+        let mut registry = registry();
+
+        for held in held.borrow().iter() {
+          if held.id == id {
+            reports.push(relock_report(&registry, held, locked));
+          } else if let Some(path) = registry.order.path(id, held.id) {
+            reports.push(inversion_report(&registry, &path, held, locked));
+          } else {
+            registry.order.add(held.id, id, || Edge {
+              held: Arc::clone(&held.locked),
+              locked: Arc::clone(locked),
+            });
+          }
+        }
+      });
+
+      // the registry is unlocked by now, so a panic doesn't poison it
+      for report in reports {
+        if PANIC.load(Ordering::SeqCst) {
+          panic!("{}", report);
+        }
+
+        eprintln!("{}", report);
+      }
+    }
+  }
+
+  fn name(registry: &Registry, id: usize) -> String {
+    match registry.created.get(&id) {
+      Some(location) => format!("mutex #{} (created at {})", id, location),
+      None => format!("mutex #{}", id),
+    }
+  }
+
+  fn relock_report(registry: &Registry, held: &Held, locked: &Backtrace) -> String {
+    format!(
+      "deadlock: {} is locked again by the thread holding it\n\n\
+       it was locked at:\n{}\n\
+       and is locked again at:\n{}",
+      name(registry, held.id),
+      held.locked,
+      locked,
+    )
+  }
+
+  // path goes from the mutex being locked to the one held, in the order they were locked before
+  fn inversion_report(registry: &Registry, path: &[usize], held: &Held, locked: &Backtrace) -> String {
+    let first = path[0];
+    let mut report = String::new();
+
+    // writing to a String doesn't fail
+    let _ = write!(
+      report,
+      "lock order inversion: {} is locked while holding {}, \
+       but before they were locked the other way around\n\n\
+       {} was locked at:\n{}\n\
+       {} is being locked at:\n{}",
+      name(registry, first),
+      name(registry, held.id),
+      name(registry, held.id),
+      held.locked,
+      name(registry, first),
+      locked,
+    );
+
+    for pair in path.windows(2) {
+      let edge = registry.order.edge(pair[0], pair[1]).unwrap();
+
+      let _ = write!(
+        report,
+        "\nbefore, {} was locked at:\n{}\n\
+         and {} was locked while holding it at:\n{}",
+        name(registry, pair[0]),
+        edge.held,
+        name(registry, pair[1]),
+        edge.locked,
+      );
+    }
+
+    report
+  }
+
+  impl<T: Default> Default for DebugMutex<T> {
+    #[track_caller]
+    fn default() -> DebugMutex<T> {
+      DebugMutex::new(T::default())
+    }
+  }
+
+  impl<T: fmt::Debug> fmt::Debug for DebugMutex<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+      f.debug_struct("DebugMutex")
+        .field("id", &self.id.0)
+        .field("inner", &self.inner)
+        .finish()
+    }
+  }
+
+  pub struct DebugGuard<'a, T> {
+    guard: MutexGuard<'a, T>,
+    id: usize,
+  }
+
+  impl<'a, T> Deref for DebugGuard<'a, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+      &self.guard
+    }
+  }
+
+  impl<'a, T> DerefMut for DebugGuard<'a, T> {
+    fn deref_mut(&mut self) -> &mut T {
+      &mut self.guard
+    }
+  }
+
+  impl<'a, T: fmt::Debug> fmt::Debug for DebugGuard<'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+      fmt::Debug::fmt(&*self.guard, f)
+    }
+  }
+
+  impl<'a, T> Drop for DebugGuard<'a, T> {
+    fn drop(&mut self) {
+      // guards don't have to be dropped in the order they were taken.
+      // try_with since a guard could be dropped by another thread local's destructor
+      let _ = HELD.try_with(|held| {
+        let mut held = held.borrow_mut();
+
+        if let Some(i) = held.iter().rposition(|held| held.id == self.id) {
+          held.remove(i);
+        }
+      });
+    }
+  }
+
+  #[cfg(test)]
+  mod tests {
+    use super::*;
+    use std::panic::{self, AssertUnwindSafe};
+    use std::thread;
+
+    fn panic_message<F: FnOnce()>(f: F) -> Option<String> {
+      let err = panic::catch_unwind(AssertUnwindSafe(f)).err()?;
+
+      match err.downcast::<String>() {
+        Ok(message) => Some(*message),
+        Err(_) => Some(String::new()),
+      }
+    }
+
+    #[test]
+    fn the_same_order_is_fine() {
+      let a = Arc::new(DebugMutex::new(0));
+      let b = Arc::new(DebugMutex::new(0));
+
+      let handles: Vec<_> = (0..4)
+        .map(|_| {
+          let (a, b) = (Arc::clone(&a), Arc::clone(&b));
+          thread::spawn(move || {
+            let mut a = a.lock().unwrap();
+            let mut b = b.lock().unwrap();
+            *a += 1;
+            *b += 1;
+          })
+        })
+        .collect();
+
+      for handle in handles {
+        handle.join().unwrap();
+      }
+
+      assert_eq!(4, *a.lock().unwrap());
+      assert_eq!(4, *b.lock().unwrap());
+    }
+
+    #[test]
+    fn the_other_order_panics() {
+      let a = DebugMutex::new(());
+      let b = DebugMutex::new(());
+
+      {
+        let _a = a.lock().unwrap();
+        let _b = b.lock().unwrap();
+      }
+
+      let message = panic_message(|| {
+        let _b = b.lock().unwrap();
+        let _a = a.lock().unwrap();
+      });
+
+      let message = message.expect("locking b then a didn't panic");
+      assert!(message.starts_with("lock order inversion"));
+      assert!(message.contains("was locked while holding it at"));
+
+      // where the path starts depends on where the crate is built from
+      let created = format!("mutex #{} (created at ", a.id.0);
+      let start = message.find(&created).expect("a isn't in the message") + created.len();
+      let location = &message[start..start + message[start..].find(')').unwrap()];
+      let file = location.rsplitn(3, ':').nth(2).unwrap();
+      assert!(file.ends_with("deadlock.rs"), "a was created at {}", location);
+
+      // a was never locked, and b was released, poisoned by the panic like a Mutex
+      assert!(a.try_lock().is_ok());
+      assert!(b.is_poisoned());
+      assert!(matches!(b.try_lock(), Err(TryLockError::Poisoned(_))));
+    }
+
+    #[test]
+    fn catches_the_inversion_across_threads() {
+      let a = Arc::new(DebugMutex::new(()));
+      let b = Arc::new(DebugMutex::new(()));
+
+      {
+        let (a, b) = (Arc::clone(&a), Arc::clone(&b));
+        thread::spawn(move || {
+          let _a = a.lock().unwrap();
+          let _b = b.lock().unwrap();
+        })
+        .join()
+        .unwrap();
+      }
+
+      let inverted = thread::spawn(move || {
+        let _b = b.lock().unwrap();
+        let _a = a.lock().unwrap();
+      });
+
+      assert!(inverted.join().is_err());
+    }
+
+    #[test]
+    fn catches_longer_cycles() {
+      let a = DebugMutex::new(());
+      let b = DebugMutex::new(());
+      let c = DebugMutex::new(());
+
+      for (first, second) in &[(&a, &b), (&b, &c)] {
+        let _first = first.lock().unwrap();
+        let _second = second.lock().unwrap();
+      }
+
+      let message = panic_message(|| {
+        let _c = c.lock().unwrap();
+        let _a = a.lock().unwrap();
+      });
+
+      // the report goes through both edges of a, b, c
+      let message = message.expect("closing the cycle didn't panic");
+      assert_eq!(2, message.matches("was locked while holding it at").count());
+    }
+
+    #[test]
+    fn locking_twice_on_the_same_thread_panics() {
+      let a = DebugMutex::new(());
+
+      let message = panic_message(|| {
+        let _first = a.lock().unwrap();
+        let _second = a.lock().unwrap();
+      });
+
+      assert!(message.unwrap().starts_with("deadlock"));
+    }
+
+    #[test]
+    fn try_lock_does_not_add_to_the_order() {
+      let a = DebugMutex::new(());
+      let b = DebugMutex::new(());
+
+      {
+        let _a = a.lock().unwrap();
+        let _b = b.try_lock().unwrap();
+      }
+
+      let _b = b.lock().unwrap();
+      let _a = a.lock().unwrap();
+    }
+
+    #[test]
+    fn guards_can_be_dropped_in_any_order() {
+      let a = DebugMutex::new(());
+      let b = DebugMutex::new(());
+
+      let guard_a = a.lock().unwrap();
+      let guard_b = b.lock().unwrap();
+      drop(guard_a);
+
+      // a isn't held anymore, so no edge from it to c
+      let c = DebugMutex::new(());
+      let _c = c.lock().unwrap();
+      drop(guard_b);
+
+      assert!(registry().order.edge(a.id.0, c.id.0).is_none());
+      assert!(registry().order.edge(b.id.0, c.id.0).is_some());
+    }
+
+    #[test]
+    fn dropped_mutexes_leave_the_registry() {
+      let b = DebugMutex::new(());
+
+      let a = {
+        let a = DebugMutex::new(());
+        let _a = a.lock().unwrap();
+        let _b = b.lock().unwrap();
+        a.id.0
+      };
+
+      let registry = registry();
+      assert!(!registry.created.contains_key(&a));
+      assert!(registry.order.edge(a, b.id.0).is_none());
+    }
+
+    #[test]
+    fn into_inner_and_poisoning() {
+      let a = DebugMutex::new(vec![1]);
+
+      let _ = panic_message(|| {
+        let mut v = a.lock().unwrap();
+        v.push(2);
+        panic!("oops");
+      });
+
+      assert!(a.is_poisoned());
+      assert_eq!(vec![1, 2], a.into_inner().unwrap_err().into_inner());
+    }
+  }
+}
@@ -0,0 +1,128 @@
+/*
+  ** The order the mutexes were locked in, as a graph: an edge from a to b
+  ** means some thread locked b while it was holding a.
+  **
+  ** Two threads can only deadlock on mutexes that some thread locked in
+  ** one order and another thread (or the same one, later) in the other, so
+  ** a new edge that closes a cycle is a deadlock waiting to happen, even
+  ** when the threads got lucky with their timing this time.
+*/
+
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, VecDeque};
+
+pub(super) struct Graph<E> {
+  edges: HashMap<usize, HashMap<usize, E>>,
+}
+
+impl<E> Graph<E> {
+  pub(super) fn new() -> Graph<E> {
+    Graph { edges: HashMap::new() }
+  }
+
+  // only the first time a pair was locked in that order is kept,
+  // so make is only called when the edge is new
+  pub(super) fn add<F>(&mut self, from: usize, to: usize, make: F)
+    where F: FnOnce() -> E
+  {
+    self.edges.entry(from).or_default().entry(to).or_insert_with(make);
+  }
+
+  pub(super) fn edge(&self, from: usize, to: usize) -> Option<&E> {
+    self.edges.get(&from)?.get(&to)
+  }
+
+  // the shortest chain of locks from `from` to `to`, both included
+  pub(super) fn path(&self, from: usize, to: usize) -> Option<Vec<usize>> {
+    // where each visited node was reached from
+    let mut came_from = HashMap::new();
+    let mut queue = VecDeque::new();
+
+    came_from.insert(from, from);
+    queue.push_back(from);
+
+    while let Some(node) = queue.pop_front() {
+      if node == to {
+        let mut path = vec![to];
+        let mut node = to;
+
+        while node != from {
+          node = came_from[&node];
+          path.push(node);
+        }
+
+        path.reverse();
+        return Some(path);
+      }
+
+      for &next in self.edges.get(&node).into_iter().flat_map(HashMap::keys) {
+        if let Entry::Vacant(entry) = came_from.entry(next) {
+          entry.insert(node);
+          queue.push_back(next);
+        }
+      }
+    }
+
+    None
+  }
+
+  // a dropped mutex can't be part of a deadlock anymore
+  pub(super) fn remove(&mut self, node: usize) {
+    self.edges.remove(&node);
+
+    for to in self.edges.values_mut() {
+      to.remove(&node);
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn finds_the_shortest_path() {
+    let mut graph = Graph::new();
+    graph.add(1, 2, || "1-2");
+    graph.add(2, 3, || "2-3");
+    graph.add(3, 4, || "3-4");
+    graph.add(1, 4, || "1-4");
+
+    assert_eq!(Some(vec![1, 4]), graph.path(1, 4));
+    assert_eq!(Some(vec![2, 3, 4]), graph.path(2, 4));
+    assert_eq!(None, graph.path(4, 1));
+    assert_eq!(Some(vec![3]), graph.path(3, 3));
+  }
+
+  #[test]
+  fn keeps_the_first_edge() {
+    let mut graph = Graph::new();
+    graph.add(1, 2, || "first");
+    graph.add(1, 2, || unreachable!());
+
+    assert_eq!(Some(&"first"), graph.edge(1, 2));
+    assert_eq!(None, graph.edge(2, 1));
+  }
+
+  #[test]
+  fn handles_cycles() {
+    let mut graph = Graph::new();
+    graph.add(1, 2, || ());
+    graph.add(2, 1, || ());
+
+    assert_eq!(Some(vec![2, 1]), graph.path(2, 1));
+    assert_eq!(None, graph.path(1, 3));
+  }
+
+  #[test]
+  fn removing_a_node_removes_its_edges() {
+    let mut graph = Graph::new();
+    graph.add(1, 2, || ());
+    graph.add(2, 3, || ());
+
+    graph.remove(2);
+
+    assert_eq!(None, graph.path(1, 3));
+    assert!(graph.edge(1, 2).is_none());
+  }
+}
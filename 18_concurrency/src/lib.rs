@@ -0,0 +1,10 @@
+pub mod channel;
+pub mod deadlock;
+pub mod pool;
+pub mod select;
+pub mod tracked;
+
+pub use deadlock::DebugMutex;
+pub use pool::ThreadPool;
+pub use select::Select;
+pub use tracked::TrackedMutex;
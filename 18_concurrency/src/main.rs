@@ -1,4 +1,7 @@
 
+// the separators between the examples print a literal on purpose
+#![allow(clippy::print_literal)]
+
 use std::thread;
 use std::time::Duration;
 
@@ -7,6 +10,9 @@ use std::time::Duration;
 // only one receiving end
 use std::sync::{mpsc, Arc, Mutex};
 
+use concurrency::channel;
+use concurrency::{DebugMutex, Select, ThreadPool, TrackedMutex};
+
 fn main() {
   println!("{}", "\n============================================\n");
 
@@ -34,6 +40,14 @@ fn main() {
 
   println!("{}", "\n============================================\n");
 
+  bounded_channel();
+
+  println!("{}", "\n============================================\n");
+
+  select_channels();
+
+  println!("{}", "\n============================================\n");
+
   single_thread_mutex();
 
   println!("{}", "\n============================================\n");
@@ -41,6 +55,18 @@ fn main() {
   multiple_thread_mutex();
 
   println!("{}", "\n============================================\n");
+
+  tracked_mutex();
+
+  println!("{}", "\n============================================\n");
+
+  lock_order();
+
+  println!("{}", "\n============================================\n");
+
+  thread_pool_mutex();
+
+  println!("{}", "\n============================================\n");
 }
 
 fn basic_threads() {
@@ -216,6 +242,65 @@ fn multiple_tx() {
   }
 }
 
+// mpsc::channel never makes the sender wait, if the receiver is slow
+// the messages just pile up, a bounded channel only holds so many
+// and send blocks until the receiver makes room for another one
+fn bounded_channel() {
+  let (tx, rx) = channel::bounded(2);
+
+  thread::spawn(move || {
+    for i in 1..=5 {
+      tx.send(i).unwrap();
+      println!("Sent: {}", i);
+    }
+  });
+
+  // the sender gets at most two messages ahead
+  for received in rx {
+    println!("Got: {}", received);
+    thread::sleep(Duration::from_millis(2));
+  }
+}
+
+// waiting on two channels at once, whichever has a message first
+fn select_channels() {
+  let (num_tx, numbers) = channel::bounded(1);
+  let (word_tx, words) = channel::bounded(1);
+
+  thread::spawn(move || {
+    for i in 1..=3 {
+      num_tx.send(i).unwrap();
+      thread::sleep(Duration::from_millis(3));
+    }
+  });
+
+  thread::spawn(move || {
+    for word in &["hi", "from", "the", "thread"] {
+      word_tx.send(*word).unwrap();
+      thread::sleep(Duration::from_millis(2));
+    }
+  });
+
+  // Err means every sender of that channel is gone, a closed channel
+  // is always ready so it's removed, or it'd be picked on every wait
+  let mut select = Select::new()
+    .recv(&numbers, |n| n.map(|n| format!("Got number: {}", n)).map_err(|_| 0))
+    .recv(&words, |w| w.map(|w| format!("Got word: {}", w)).map_err(|_| 1))
+    .timeout(Duration::from_millis(50), || Ok(String::from("Nothing yet")));
+
+  let mut open = 2;
+
+  while open > 0 {
+    match select.wait() {
+      Ok(message) => println!("{}", message),
+      Err(channel) => {
+        select.remove(channel);
+        open -= 1;
+      },
+    }
+  }
+}
+
 fn single_thread_mutex() {
   let m = Mutex::new(5);
 
@@ -279,4 +364,88 @@ fn multiple_thread_mutex() {
   }
 
   println!("Result: {}", *counter.lock().unwrap());
-}
\ No newline at end of file
+}
+
+// the same counter again, this time keeping track of how much
+// the threads had to wait for each other to get the lock
+fn tracked_mutex() {
+  let counter = Arc::new(TrackedMutex::new(0));
+  let mut handles = vec![];
+
+  for _ in 0..10 {
+    let counter = Arc::clone(&counter);
+    let handle = thread::spawn(move || {
+      let mut num = counter.lock().unwrap();
+
+      *num += 1;
+    });
+
+    handles.push(handle);
+  }
+
+  for handle in handles {
+    handle.join().unwrap();
+  }
+
+  // the report is taken first, so reading the result isn't counted
+  let report = counter.report();
+
+  println!("Result: {}", *counter.lock().unwrap());
+  println!("{}", report);
+}
+
+// two threads moving money between the same two accounts
+// both lock `from` first and `to` second, if one of them
+// did it the other way around a debug build would panic,
+// even when the threads didn't happen to deadlock this time
+fn lock_order() {
+  let from = Arc::new(DebugMutex::new(100));
+  let to = Arc::new(DebugMutex::new(0));
+  let mut handles = vec![];
+
+  for amount in &[10, 25] {
+    let (from, to) = (Arc::clone(&from), Arc::clone(&to));
+    let amount = *amount;
+
+    let handle = thread::spawn(move || {
+      let mut from = from.lock().unwrap();
+      let mut to = to.lock().unwrap();
+
+      *from -= amount;
+      *to += amount;
+    });
+
+    handles.push(handle);
+  }
+
+  for handle in handles {
+    handle.join().unwrap();
+  }
+
+  println!("From: {}, to: {}", *from.lock().unwrap(), *to.lock().unwrap());
+}
+
+// the same counter as above, but instead of spawning
+// a thread for each of the ten increments, a pool of
+// four threads takes turns running them
+fn thread_pool_mutex() {
+  let counter = Arc::new(Mutex::new(0));
+
+  {
+    let pool = ThreadPool::new(4);
+
+    for _ in 0..10 {
+      let counter = Arc::clone(&counter);
+      pool.execute(move || {
+        let mut num = counter.lock().unwrap();
+
+        *num += 1;
+      });
+    }
+
+    // dropping the pool waits for every job to run
+    // and for its threads to finish, like joining the handles
+  }
+
+  println!("Result: {}", *counter.lock().unwrap());
+}
@@ -0,0 +1,176 @@
+/*
+  ** A ThreadPool keeps a fixed number of threads around and hands them
+  ** jobs through a channel, instead of spawning a thread for every task.
+  **
+  ** The channel is the same mpsc channel used in main.rs. There is one
+  ** sending end, kept by the pool, and a single receiving end shared by all
+  ** the workers behind an Arc<Mutex<T>>, so each job is only taken by one
+  ** of them. Dropping the pool drops the sending end: the workers finish
+  ** the jobs that were already queued, see the channel is closed and stop,
+  ** and the pool joins every one of them before it's gone.
+  **
+  ** A job that panics doesn't take its worker down with it. The panic is
+  ** caught, counted, and the worker goes back to waiting for the next job.
+*/
+
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread::{self, JoinHandle};
+
+type Job = Box<dyn FnOnce() + Send + 'static>;
+
+pub struct ThreadPool {
+  workers: Vec<Worker>,
+  // None once the pool is shutting down
+  sender: Option<mpsc::Sender<Job>>,
+  panics: Arc<AtomicUsize>,
+}
+
+impl ThreadPool {
+  // panics when size is 0, a pool without threads would never run anything
+  pub fn new(size: usize) -> ThreadPool {
+    assert!(size > 0, "a ThreadPool needs at least one thread");
+
+    let (sender, receiver) = mpsc::channel();
+    let receiver = Arc::new(Mutex::new(receiver));
+    let panics = Arc::new(AtomicUsize::new(0));
+
+    let workers = (0..size)
+      .map(|id| Worker::new(id, Arc::clone(&receiver), Arc::clone(&panics)))
+      .collect();
+
+    ThreadPool {
+      workers,
+      sender: Some(sender),
+      panics,
+    }
+  }
+
+  // queues the job, it runs as soon as one of the workers is free
+  pub fn execute<F>(&self, f: F)
+    where F: FnOnce() + Send + 'static
+  {
+    // the workers only stop after the sender is dropped,
+    // so while the pool is around they are there to receive
+    self.sender.as_ref().unwrap().send(Box::new(f)).unwrap();
+  }
+
+  pub fn size(&self) -> usize {
+    self.workers.len()
+  }
+
+  // how many jobs panicked so far
+  pub fn panicked(&self) -> usize {
+    self.panics.load(Ordering::SeqCst)
+  }
+}
+
+impl Drop for ThreadPool {
+  fn drop(&mut self) {
+    // closing the channel is what tells the workers to stop
+    drop(self.sender.take());
+
+    for worker in &mut self.workers {
+      if let Some(thread) = worker.thread.take() {
+        thread.join().unwrap();
+      }
+    }
+  }
+}
+
+struct Worker {
+  // None once the worker was joined
+  thread: Option<JoinHandle<()>>,
+}
+
+impl Worker {
+  fn new(id: usize, receiver: Arc<Mutex<mpsc::Receiver<Job>>>, panics: Arc<AtomicUsize>) -> Worker {
+    let thread = thread::Builder::new()
+      .name(format!("pool-worker-{}", id))
+      .spawn(move || loop {
+        // the lock is released at the end of this statement, so the
+        // other workers can take jobs while this one runs its own
+        let message = receiver.lock().unwrap().recv();
+
+        match message {
+          Ok(job) => {
+            // the job doesn't share anything with the loop,
+            // so nothing is left half changed if it panics
+            if panic::catch_unwind(AssertUnwindSafe(job)).is_err() {
+              panics.fetch_add(1, Ordering::SeqCst);
+            }
+          },
+          // the pool was dropped and every queued job was taken
+          Err(_) => break,
+        }
+      })
+      .unwrap();
+
+    Worker { thread: Some(thread) }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::collections::HashSet;
+  use std::time::Duration;
+
+  #[test]
+  fn runs_every_job_before_shutting_down() {
+    let counter = Arc::new(Mutex::new(0));
+
+    {
+      let pool = ThreadPool::new(4);
+
+      for _ in 0..100 {
+        let counter = Arc::clone(&counter);
+        pool.execute(move || {
+          thread::sleep(Duration::from_micros(100));
+          *counter.lock().unwrap() += 1;
+        });
+      }
+      // dropping the pool waits for the queued jobs
+    }
+
+    assert_eq!(100, *counter.lock().unwrap());
+  }
+
+  #[test]
+  fn reuses_its_threads() {
+    let threads = Arc::new(Mutex::new(HashSet::new()));
+
+    {
+      let pool = ThreadPool::new(3);
+      for _ in 0..30 {
+        let threads = Arc::clone(&threads);
+        pool.execute(move || {
+          threads.lock().unwrap().insert(thread::current().id());
+        });
+      }
+    }
+
+    let threads = threads.lock().unwrap();
+    assert!(!threads.is_empty() && threads.len() <= 3);
+    assert!(!threads.contains(&thread::current().id()));
+  }
+
+  #[test]
+  fn a_panicking_job_does_not_kill_its_worker() {
+    let (tx, rx) = mpsc::channel();
+    let pool = ThreadPool::new(1);
+
+    pool.execute(|| panic!("job failed"));
+    pool.execute(move || tx.send("still working").unwrap());
+
+    assert_eq!(Ok("still working"), rx.recv_timeout(Duration::from_secs(5)));
+    assert_eq!(1, pool.panicked());
+  }
+
+  #[test]
+  #[should_panic]
+  fn zero_threads_panics() {
+    ThreadPool::new(0);
+  }
+}
@@ -0,0 +1,385 @@
+/*
+  ** Waits on many channels at once, even when they carry different types,
+  ** and runs the closure of the first one that has something:
+  **
+  **   let mut select = Select::new()
+  **     .recv(&numbers, |n| format!("number {:?}", n))
+  **     .recv(&words, |w| format!("word {:?}", w))
+  **     .timeout(Duration::from_secs(1), || String::from("nothing"));
+  **
+  **   let what = select.wait();
+  **
+  ** A channel whose senders are all gone is ready too, its closure gets the
+  ** RecvError, the same way recv would return it right away. It stays ready
+  ** on every wait after that, so once the RecvError was handled the channel
+  ** should be taken out with remove, given its position among the recv calls.
+  **
+  ** The same Select can wait many times. It's fair: every wait starts
+  ** looking right after the channel picked the last time, so a busy channel
+  ** listed first can't keep the ones after it from ever being picked.
+  **
+  ** While nothing is ready the thread sleeps on a Signal that is handed to
+  ** every channel, and any of them wakes it up when a message is sent or
+  ** when its last Sender is dropped.
+*/
+
+use std::sync::mpsc::{RecvError, TryRecvError};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::channel::Receiver;
+
+pub(crate) struct Signal {
+  notified: Mutex<bool>,
+  cond: Condvar,
+}
+
+impl Signal {
+  fn new() -> Signal {
+    Signal {
+      notified: Mutex::new(false),
+      cond: Condvar::new(),
+    }
+  }
+
+  pub(crate) fn notify(&self) {
+    *self.notified.lock().unwrap() = true;
+    self.cond.notify_all();
+  }
+
+  // false if the deadline passed without a notification
+  // a notification is only seen once, so the same signal can be
+  // waited on again after the message it was about was taken
+  fn wait(&self, deadline: Option<Instant>) -> bool {
+    let mut notified = self.notified.lock().unwrap();
+
+    while !*notified {
+      notified = match deadline {
+        None => self.cond.wait(notified).unwrap(),
+        Some(deadline) => {
+          let now = Instant::now();
+          if now >= deadline {
+            return false;
+          }
+          self.cond.wait_timeout(notified, deadline - now).unwrap().0
+        },
+      };
+    }
+
+    *notified = false;
+    true
+  }
+}
+
+// a channel to wait on, together with what to do with its message
+trait Arm<R> {
+  // runs the closure if the channel has a message or was disconnected
+  fn try_select(&mut self) -> Option<R>;
+  fn watch(&self, signal: &Arc<Signal>);
+  fn unwatch(&self, signal: &Arc<Signal>);
+}
+
+struct RecvArm<'a, T, F> {
+  rx: &'a Receiver<T>,
+  f: F,
+}
+
+impl<'a, T, F, R> Arm<R> for RecvArm<'a, T, F>
+  where F: FnMut(Result<T, RecvError>) -> R
+{
+  fn try_select(&mut self) -> Option<R> {
+    match self.rx.try_recv() {
+      Ok(value) => Some((self.f)(Ok(value))),
+      Err(TryRecvError::Disconnected) => Some((self.f)(Err(RecvError))),
+      Err(TryRecvError::Empty) => None,
+    }
+  }
+
+  fn watch(&self, signal: &Arc<Signal>) {
+    self.rx.watch(signal);
+  }
+
+  fn unwatch(&self, signal: &Arc<Signal>) {
+    self.rx.unwatch(signal);
+  }
+}
+
+// what happens when no channel is ready
+struct Fallback<'a, R> {
+  // None doesn't wait at all
+  timeout: Option<Duration>,
+  f: Box<dyn FnMut() -> R + 'a>,
+}
+
+pub struct Select<'a, R> {
+  // a removed arm leaves None behind so the others keep their index
+  arms: Vec<Option<Box<dyn Arm<R> + 'a>>>,
+  default: Option<Fallback<'a, R>>,
+  // where the next wait starts looking
+  next: usize,
+}
+
+impl<'a, R> Select<'a, R> {
+  pub fn new() -> Select<'a, R> {
+    Select {
+      arms: Vec::new(),
+      default: None,
+      next: 0,
+    }
+  }
+
+  pub fn recv<T, F>(mut self, rx: &'a Receiver<T>, f: F) -> Select<'a, R>
+    where T: 'a,
+          F: FnMut(Result<T, RecvError>) -> R + 'a
+  {
+    self.arms.push(Some(Box::new(RecvArm { rx, f })));
+    self
+  }
+
+  // runs f right away when no channel is ready, instead of blocking
+  pub fn default<F>(mut self, f: F) -> Select<'a, R>
+    where F: FnMut() -> R + 'a
+  {
+    self.default = Some(Fallback { timeout: None, f: Box::new(f) });
+    self
+  }
+
+  // runs f when no channel got ready within timeout
+  pub fn timeout<F>(mut self, timeout: Duration, f: F) -> Select<'a, R>
+    where F: FnMut() -> R + 'a
+  {
+    self.default = Some(Fallback { timeout: Some(timeout), f: Box::new(f) });
+    self
+  }
+
+  // stops waiting on the channel of the index-th recv call, counting from 0
+  // the other channels keep their index
+  pub fn remove(&mut self, index: usize) {
+    assert!(index < self.arms.len(), "no channel at index {}", index);
+    self.arms[index] = None;
+  }
+
+  // blocks until one of the channels is ready, or until the default runs
+  // panics when there is nothing to wait on, since that would be forever
+  pub fn wait(&mut self) -> R {
+    assert!(
+      self.arms.iter().any(Option::is_some) || self.default.is_some(),
+      "a Select needs a channel or a default to wait on"
+    );
+
+    let deadline = match &self.default {
+      Some(Fallback { timeout: Some(timeout), .. }) => Instant::now().checked_add(*timeout),
+      _ => None,
+    };
+
+    if let Some(r) = self.try_select() {
+      return r;
+    }
+    if let Some(Fallback { timeout: None, f }) = &mut self.default {
+      return f();
+    }
+
+    let signal = Arc::new(Signal::new());
+    for arm in self.arms.iter().flatten() {
+      arm.watch(&signal);
+    }
+
+    // a message sent before the signal was handed to its channel didn't
+    // notify it, so look once more before sleeping, and again after every
+    // wake up since another receiver may have taken the message first
+    let selected = loop {
+      if let Some(r) = self.try_select() {
+        break r;
+      }
+      if !signal.wait(deadline) {
+        break (self.default.as_mut().unwrap().f)();
+      }
+    };
+
+    for arm in self.arms.iter().flatten() {
+      arm.unwatch(&signal);
+    }
+
+    selected
+  }
+
+  fn try_select(&mut self) -> Option<R> {
+    let len = self.arms.len();
+
+    for k in 0..len {
+      let i = (self.next + k) % len;
+
+      if let Some(r) = self.arms[i].as_mut().and_then(|arm| arm.try_select()) {
+        self.next = (i + 1) % len;
+        return Some(r);
+      }
+    }
+
+    None
+  }
+}
+
+impl<'a, R> Default for Select<'a, R> {
+  fn default() -> Select<'a, R> {
+    Select::new()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::channel::bounded;
+  use std::thread;
+
+  #[derive(Debug, PartialEq)]
+  enum Got {
+    Number(u32),
+    Word(&'static str),
+    Closed,
+    Nothing,
+  }
+
+  #[test]
+  fn picks_the_ready_channel() {
+    let (num_tx, numbers) = bounded(1);
+    let (word_tx, words) = bounded(1);
+
+    let mut select = Select::new()
+      .recv(&numbers, |n| n.map(Got::Number).unwrap_or(Got::Closed))
+      .recv(&words, |w| w.map(Got::Word).unwrap_or(Got::Closed));
+
+    word_tx.send("hi").unwrap();
+    assert_eq!(Got::Word("hi"), select.wait());
+
+    num_tx.send(7).unwrap();
+    assert_eq!(Got::Number(7), select.wait());
+  }
+
+  #[test]
+  fn blocks_until_a_message_is_sent() {
+    let (num_tx, numbers) = bounded(1);
+    let (_word_tx, words) = bounded::<&str>(1);
+
+    let sender = thread::spawn(move || {
+      thread::sleep(Duration::from_millis(20));
+      num_tx.send(42).unwrap();
+      // keeps the channel open until the message was received
+      num_tx
+    });
+
+    let got = Select::new()
+      .recv(&words, |w| w.map(Got::Word).unwrap_or(Got::Closed))
+      .recv(&numbers, |n| n.map(Got::Number).unwrap_or(Got::Closed))
+      .wait();
+
+    assert_eq!(Got::Number(42), got);
+    sender.join().unwrap();
+  }
+
+  #[test]
+  fn default_and_timeout() {
+    let (_tx, rx) = bounded::<u32>(1);
+
+    let got = Select::new()
+      .recv(&rx, |n| n.map(Got::Number).unwrap_or(Got::Closed))
+      .default(|| Got::Nothing)
+      .wait();
+    assert_eq!(Got::Nothing, got);
+
+    let start = Instant::now();
+    let got = Select::new()
+      .recv(&rx, |n| n.map(Got::Number).unwrap_or(Got::Closed))
+      .timeout(Duration::from_millis(20), || Got::Nothing)
+      .wait();
+    assert_eq!(Got::Nothing, got);
+    assert!(start.elapsed() >= Duration::from_millis(20));
+  }
+
+  #[test]
+  fn disconnected_channels_are_ready() {
+    let (tx, rx) = bounded::<u32>(1);
+
+    let waiting = thread::spawn(move || {
+      Select::new()
+        .recv(&rx, |n| n.map(Got::Number).unwrap_or(Got::Closed))
+        .wait()
+    });
+
+    thread::sleep(Duration::from_millis(20));
+    drop(tx);
+
+    assert_eq!(Got::Closed, waiting.join().unwrap());
+  }
+
+  #[test]
+  fn removed_channels_are_not_picked() {
+    let (tx, closed) = bounded::<u32>(1);
+    drop(tx);
+    let (word_tx, words) = bounded(1);
+
+    let mut select = Select::new()
+      .recv(&closed, |n| n.map(Got::Number).unwrap_or(Got::Closed))
+      .recv(&words, |w| w.map(Got::Word).unwrap_or(Got::Closed))
+      .timeout(Duration::from_millis(1), || Got::Nothing);
+
+    assert_eq!(Got::Closed, select.wait());
+    select.remove(0);
+    assert_eq!(Got::Nothing, select.wait());
+
+    word_tx.send("hi").unwrap();
+    assert_eq!(Got::Word("hi"), select.wait());
+  }
+
+  #[test]
+  #[should_panic(expected = "a Select needs a channel or a default to wait on")]
+  fn removing_every_channel_leaves_nothing_to_wait_on() {
+    let (_tx, rx) = bounded::<u32>(1);
+    let mut select = Select::new().recv(&rx, |n| n.is_ok());
+
+    select.remove(0);
+    select.wait();
+  }
+
+  #[test]
+  fn busy_channels_do_not_starve_the_others() {
+    let (a_tx, a) = bounded(100);
+    let (b_tx, b) = bounded(100);
+
+    for _ in 0..100 {
+      a_tx.send('a').unwrap();
+      b_tx.send('b').unwrap();
+    }
+
+    let mut select = Select::new().recv(&a, |c| c.unwrap()).recv(&b, |c| c.unwrap());
+    let picked: String = (0..10).map(|_| select.wait()).collect();
+
+    assert_eq!("ababababab", picked);
+  }
+
+  #[test]
+  fn selects_again_after_a_timeout() {
+    let (tx, rx) = bounded::<u32>(1);
+    let mut select = Select::new()
+      .recv(&rx, |n| n.map(Got::Number).unwrap_or(Got::Closed))
+      .timeout(Duration::from_millis(1), || Got::Nothing);
+
+    assert_eq!(Got::Nothing, select.wait());
+    tx.send(3).unwrap();
+    assert_eq!(Got::Number(3), select.wait());
+  }
+
+  #[test]
+  fn a_notification_is_seen_once() {
+    let signal = Signal::new();
+    signal.notify();
+
+    assert!(signal.wait(None));
+    assert!(!signal.wait(Some(Instant::now())));
+  }
+
+  #[test]
+  #[should_panic]
+  fn nothing_to_wait_on_panics() {
+    Select::<()>::new().wait();
+  }
+}
@@ -0,0 +1,364 @@
+/*
+  ** A Mutex that keeps track of how it's used, to see how much the threads
+  ** sharing it get in each other's way:
+  **
+  **   - how many times it was locked, and how many of those had to wait
+  **     because another thread was holding it
+  **   - how long the threads spent waiting for it, in total
+  **   - the longest any thread held it
+  **   - how many times a thread panicked while holding it, poisoning it
+  **
+  ** Lots of waiting compared to the number of locks means the threads are
+  ** mostly taking turns instead of running side by side. If the lock is
+  ** only held for a moment, like for a counter, an atomic would do instead,
+  ** otherwise the data could be split so the threads don't all need the
+  ** same lock.
+  **
+  ** The numbers are kept in atomics, so getting a report doesn't need the
+  ** lock and doesn't count as using it. Each number is read on its own, so
+  ** a report taken while the mutex is in use can be a lock or two behind
+  ** in some of them.
+*/
+
+use std::convert::TryFrom;
+use std::fmt;
+use std::ops::{Deref, DerefMut};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{LockResult, Mutex, MutexGuard, PoisonError, TryLockError, TryLockResult};
+use std::thread;
+use std::time::{Duration, Instant};
+
+#[derive(Default)]
+struct Stats {
+  acquisitions: AtomicU64,
+  contended: AtomicU64,
+  // in nanoseconds
+  total_wait: AtomicU64,
+  total_hold: AtomicU64,
+  max_hold: AtomicU64,
+  poisonings: AtomicU64,
+}
+
+// a u64 of nanoseconds is over 500 years
+fn nanos(d: Duration) -> u64 {
+  u64::try_from(d.as_nanos()).unwrap_or(u64::MAX)
+}
+
+#[derive(Default)]
+pub struct TrackedMutex<T> {
+  inner: Mutex<T>,
+  stats: Stats,
+}
+
+impl<T> TrackedMutex<T> {
+  pub fn new(value: T) -> TrackedMutex<T> {
+    TrackedMutex {
+      inner: Mutex::new(value),
+      stats: Stats::default(),
+    }
+  }
+
+  // the same as Mutex::lock, and it's poisoned the same way
+  pub fn lock(&self) -> LockResult<TrackedGuard<'_, T>> {
+    let start = Instant::now();
+
+    // trying first tells apart the locks that had to wait
+    let result = match self.inner.try_lock() {
+      Ok(guard) => Ok(guard),
+      Err(TryLockError::Poisoned(err)) => Err(err),
+      Err(TryLockError::WouldBlock) => {
+        self.stats.contended.fetch_add(1, Ordering::Relaxed);
+        self.inner.lock()
+      },
+    };
+
+    let waited = start.elapsed();
+
+    match result {
+      Ok(guard) => Ok(self.guard(guard, waited)),
+      Err(err) => Err(PoisonError::new(self.guard(err.into_inner(), waited))),
+    }
+  }
+
+  // the same as Mutex::try_lock, failing to lock isn't counted
+  pub fn try_lock(&self) -> TryLockResult<TrackedGuard<'_, T>> {
+    match self.inner.try_lock() {
+      Ok(guard) => Ok(self.guard(guard, Duration::from_secs(0))),
+      Err(TryLockError::Poisoned(err)) => {
+        let guard = self.guard(err.into_inner(), Duration::from_secs(0));
+        Err(TryLockError::Poisoned(PoisonError::new(guard)))
+      },
+      Err(TryLockError::WouldBlock) => Err(TryLockError::WouldBlock),
+    }
+  }
+
+  pub fn is_poisoned(&self) -> bool {
+    self.inner.is_poisoned()
+  }
+
+  pub fn into_inner(self) -> LockResult<T> {
+    self.inner.into_inner()
+  }
+
+  pub fn get_mut(&mut self) -> LockResult<&mut T> {
+    self.inner.get_mut()
+  }
+
+  pub fn report(&self) -> Report {
+    let stats = &self.stats;
+    let duration = |n: &AtomicU64| Duration::from_nanos(n.load(Ordering::Relaxed));
+
+    Report {
+      acquisitions: stats.acquisitions.load(Ordering::Relaxed),
+      contended: stats.contended.load(Ordering::Relaxed),
+      total_wait: duration(&stats.total_wait),
+      total_hold: duration(&stats.total_hold),
+      max_hold: duration(&stats.max_hold),
+      poisonings: stats.poisonings.load(Ordering::Relaxed),
+    }
+  }
+
+  fn guard<'a>(&'a self, guard: MutexGuard<'a, T>, waited: Duration) -> TrackedGuard<'a, T> {
+    self.stats.acquisitions.fetch_add(1, Ordering::Relaxed);
+    self.stats.total_wait.fetch_add(nanos(waited), Ordering::Relaxed);
+
+    TrackedGuard {
+      guard,
+      stats: &self.stats,
+      locked_at: Instant::now(),
+      panicking: thread::panicking(),
+    }
+  }
+}
+
+impl<T: fmt::Debug> fmt::Debug for TrackedMutex<T> {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    f.debug_struct("TrackedMutex")
+      .field("inner", &self.inner)
+      .field("report", &self.report())
+      .finish()
+  }
+}
+
+pub struct TrackedGuard<'a, T> {
+  guard: MutexGuard<'a, T>,
+  stats: &'a Stats,
+  locked_at: Instant,
+  // a lock taken while already unwinding, e.g. in a Drop,
+  // doesn't poison the mutex when it's let go
+  panicking: bool,
+}
+
+impl<'a, T> Deref for TrackedGuard<'a, T> {
+  type Target = T;
+
+  fn deref(&self) -> &T {
+    &self.guard
+  }
+}
+
+impl<'a, T> DerefMut for TrackedGuard<'a, T> {
+  fn deref_mut(&mut self) -> &mut T {
+    &mut self.guard
+  }
+}
+
+impl<'a, T: fmt::Debug> fmt::Debug for TrackedGuard<'a, T> {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    fmt::Debug::fmt(&*self.guard, f)
+  }
+}
+
+impl<'a, T> Drop for TrackedGuard<'a, T> {
+  fn drop(&mut self) {
+    let held = nanos(self.locked_at.elapsed());
+
+    self.stats.total_hold.fetch_add(held, Ordering::Relaxed);
+    self.stats.max_hold.fetch_max(held, Ordering::Relaxed);
+
+    // the MutexGuard is dropped right after this, and it poisons
+    // the mutex if the thread started panicking while holding it
+    if thread::panicking() && !self.panicking {
+      self.stats.poisonings.fetch_add(1, Ordering::Relaxed);
+    }
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Report {
+  pub acquisitions: u64,
+  // the acquisitions that had to wait for another thread
+  pub contended: u64,
+  pub total_wait: Duration,
+  pub total_hold: Duration,
+  pub max_hold: Duration,
+  // the times a thread panicked while holding the lock
+  pub poisonings: u64,
+}
+
+impl Report {
+  // how many of the acquisitions had to wait, from 0 to 1
+  pub fn contention(&self) -> f64 {
+    if self.acquisitions == 0 {
+      return 0.0;
+    }
+
+    self.contended as f64 / self.acquisitions as f64
+  }
+
+  pub fn average_wait(&self) -> Duration {
+    match u32::try_from(self.acquisitions) {
+      Ok(0) => Duration::from_secs(0),
+      Ok(n) => self.total_wait / n,
+      Err(_) => Duration::from_nanos(nanos(self.total_wait) / self.acquisitions),
+    }
+  }
+}
+
+impl fmt::Display for Report {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    writeln!(f, "Acquisitions: {} ({} contended, {:.0}%)", self.acquisitions, self.contended, self.contention() * 100.0)?;
+    writeln!(f, "Waited: {:?} in total, {:?} on average", self.total_wait, self.average_wait())?;
+    writeln!(f, "Held: {:?} in total, {:?} at most", self.total_hold, self.max_hold)?;
+    write!(f, "Poisoned: {} times", self.poisonings)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::panic::{self, AssertUnwindSafe};
+  use std::sync::Arc;
+
+  #[test]
+  fn counts_every_lock() {
+    let counter = Arc::new(TrackedMutex::new(0));
+
+    let handles: Vec<_> = (0..10)
+      .map(|_| {
+        let counter = Arc::clone(&counter);
+        thread::spawn(move || {
+          for _ in 0..10 {
+            *counter.lock().unwrap() += 1;
+          }
+        })
+      })
+      .collect();
+
+    for handle in handles {
+      handle.join().unwrap();
+    }
+
+    let report = counter.report();
+    assert_eq!(100, *counter.lock().unwrap());
+    assert_eq!(100, report.acquisitions);
+    assert!(report.contended <= report.acquisitions);
+    assert_eq!(0, report.poisonings);
+  }
+
+  #[test]
+  fn waiting_for_another_thread() {
+    let m = Arc::new(TrackedMutex::new(()));
+    let guard = m.lock().unwrap();
+
+    let waiting = {
+      let m = Arc::clone(&m);
+      thread::spawn(move || {
+        let _guard = m.lock().unwrap();
+      })
+    };
+
+    // the waiter counts itself as contended before it blocks,
+    // from then on it's waiting for the guard to be dropped
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while m.report().contended == 0 {
+      assert!(Instant::now() < deadline, "the other thread never tried to lock");
+      thread::yield_now();
+    }
+
+    thread::sleep(Duration::from_millis(20));
+    drop(guard);
+    waiting.join().unwrap();
+
+    let report = m.report();
+    assert_eq!(2, report.acquisitions);
+    assert_eq!(1, report.contended);
+    assert_eq!(0.5, report.contention());
+    assert!(report.total_wait >= Duration::from_millis(20));
+    assert!(report.max_hold >= Duration::from_millis(20));
+    assert!(report.total_hold >= report.max_hold);
+  }
+
+  #[test]
+  fn a_failed_try_lock_is_not_counted() {
+    let m = TrackedMutex::new(1);
+    let guard = m.lock().unwrap();
+
+    assert!(matches!(m.try_lock(), Err(TryLockError::WouldBlock)));
+    drop(guard);
+    assert_eq!(Some(1), m.try_lock().ok().map(|guard| *guard));
+
+    let report = m.report();
+    assert_eq!(2, report.acquisitions);
+    assert_eq!(0, report.contended);
+  }
+
+  #[test]
+  fn panicking_while_holding_the_lock() {
+    let m = TrackedMutex::new(vec![1]);
+
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+      let mut v = m.lock().unwrap();
+      v.push(2);
+      panic!("oops");
+    }));
+
+    assert!(result.is_err());
+    assert!(m.is_poisoned());
+    assert_eq!(1, m.report().poisonings);
+
+    // the data is still there, like with a Mutex
+    let v = m.lock().unwrap_err().into_inner();
+    assert_eq!(vec![1, 2], *v);
+    drop(v);
+
+    let report = m.report();
+    assert_eq!(2, report.acquisitions);
+    assert_eq!(1, report.poisonings);
+  }
+
+  #[test]
+  fn locking_while_unwinding_does_not_poison() {
+    struct LocksOnDrop<'a>(&'a TrackedMutex<u32>);
+
+    impl<'a> Drop for LocksOnDrop<'a> {
+      fn drop(&mut self) {
+        *self.0.lock().unwrap() += 1;
+      }
+    }
+
+    let m = TrackedMutex::new(0);
+
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+      let _cleanup = LocksOnDrop(&m);
+      panic!("oops");
+    }));
+
+    assert!(result.is_err());
+    assert!(!m.is_poisoned());
+    assert_eq!(1, *m.lock().unwrap());
+    assert_eq!(0, m.report().poisonings);
+  }
+
+  #[test]
+  fn an_unused_mutex_reports_nothing() {
+    let report = TrackedMutex::new(0).report();
+
+    assert_eq!(0.0, report.contention());
+    assert_eq!(Duration::from_secs(0), report.average_wait());
+    assert_eq!("Acquisitions: 0 (0 contended, 0%)\n\
+                Waited: 0ns in total, 0ns on average\n\
+                Held: 0ns in total, 0ns at most\n\
+                Poisoned: 0 times", report.to_string());
+  }
+}
@@ -0,0 +1,35 @@
+/*
+  * Prints the blog post workflow as a diagram
+    *** cargo run --bin diagram            -> Mermaid, to embed in markdown docs
+    *** cargo run --bin diagram -- dot     -> Graphviz DOT, e.g. piped into `dot -Tsvg`
+    *** add --loops to also draw the actions that don't change the state
+*/
+
+use std::env;
+use std::process;
+
+use oop::diagram::Diagram;
+use oop::registry::Registry;
+
+fn main() {
+  let mut format = String::from("mermaid");
+  let mut loops = false;
+
+  for arg in env::args().skip(1) {
+    match arg.as_str() {
+      "--loops" => loops = true,
+      "dot" | "mermaid" => format = arg,
+      _ => {
+        eprintln!("usage: diagram [dot|mermaid] [--loops]");
+        process::exit(1);
+      },
+    }
+  }
+
+  let diagram = Diagram::new(&Registry::new()).with_loops(loops);
+
+  match format.as_str() {
+    "dot" => print!("{}", diagram.to_dot()),
+    _ => print!("{}", diagram.to_mermaid()),
+  }
+}
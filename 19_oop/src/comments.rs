@@ -0,0 +1,240 @@
+/*
+  ** Review comments let a reviewer tell the author why a post bounced.
+  ** A comment either talks about the whole post, like the reason given
+  ** to reject_with, or is anchored to a byte range of the content.
+  **
+  ** Anchored comments remember the text they were made on, so when the
+  ** content is edited they can follow that text around. When the text they
+  ** pointed at was changed they are kept open but marked as outdated.
+*/
+
+use std::ops::Range;
+
+use serde::{Deserialize, Serialize};
+
+use crate::Post;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Comment {
+  pub id: usize,
+  pub author: Option<String>,
+  pub body: String,
+  // None for comments about the whole post
+  pub anchor: Option<Range<usize>>,
+  // the text under the anchor when the comment was made
+  pub quote: String,
+  // the revision the comment was made on
+  pub revision: usize,
+  pub outdated: bool,
+  pub resolved: bool,
+}
+
+impl Post {
+  // rejects the post and leaves the reason for the author
+  // the comment is only added when the post actually went back to Draft
+  pub fn reject_with(&mut self, reason: &str) {
+    if self.transition("reject", None, |s, _| s.reject()) {
+      self.push_comment(None, reason, None);
+    }
+  }
+
+  // the range has to be inside the content and fall on char boundaries,
+  // otherwise no comment is added and None is returned
+  pub fn add_comment(&mut self, author: &str, anchor: Range<usize>, body: &str) -> Option<usize> {
+    self.content.get(anchor.clone())?;
+
+    Some(self.push_comment(Some(author), body, Some(anchor)))
+  }
+
+  pub fn resolve_comment(&mut self, id: usize) -> bool {
+    match self.comments.iter_mut().find(|c| c.id == id) {
+      Some(comment) if !comment.resolved => {
+        comment.resolved = true;
+        true
+      },
+      _ => false,
+    }
+  }
+
+  pub fn comments(&self) -> &[Comment] {
+    &self.comments
+  }
+
+  // the comments that weren't resolved yet, including the outdated ones
+  pub fn open_comments(&self) -> Vec<&Comment> {
+    self.comments.iter().filter(|c| !c.resolved).collect()
+  }
+
+  fn push_comment(&mut self, author: Option<&str>, body: &str, anchor: Option<Range<usize>>) -> usize {
+    let id = self.comments.len();
+    let quote = match &anchor {
+      Some(range) => self.content[range.clone()].to_string(),
+      None => String::new(),
+    };
+
+    self.comments.push(Comment {
+      id,
+      author: author.map(String::from),
+      body: body.to_string(),
+      anchor,
+      quote,
+      revision: self.revision(),
+      outdated: false,
+      resolved: false,
+    });
+
+    id
+  }
+}
+
+/*
+  ** Moves the anchors of open comments after the content went from old to new.
+  ** The bytes both texts start and end with didn't change, so an anchor inside
+  ** that prefix stays where it is and one inside that suffix moves by how much
+  ** the text grew or shrank. Anything else is looked up by its quote and only
+  ** moved when the quote shows up exactly once in the new text.
+*/
+pub(crate) fn reanchor(comments: &mut [Comment], old: &str, new: &str) {
+  let prefix = common_prefix(old, new);
+  let suffix = common_suffix(&old[prefix..], &new[prefix..]);
+  let suffix_start = old.len() - suffix;
+
+  for comment in comments.iter_mut() {
+    if comment.resolved || comment.outdated {
+      continue;
+    }
+
+    let range = match &comment.anchor {
+      Some(range) => range.clone(),
+      None => continue,
+    };
+
+    if range.end <= prefix {
+      continue;
+    }
+
+    if range.start >= suffix_start {
+      let start = range.start - suffix_start + (new.len() - suffix);
+      comment.anchor = Some(start..start + range.len());
+      continue;
+    }
+
+    let mut found = new.match_indices(comment.quote.as_str());
+
+    match (found.next(), found.next()) {
+      (Some((start, _)), None) if !comment.quote.is_empty() => {
+        comment.anchor = Some(start..start + comment.quote.len());
+      },
+      _ => comment.outdated = true,
+    }
+  }
+}
+
+// the length in bytes both texts start with, ending on a char boundary
+fn common_prefix(a: &str, b: &str) -> usize {
+  let mut len = a.bytes().zip(b.bytes()).take_while(|(x, y)| x == y).count();
+
+  while !a.is_char_boundary(len) {
+    len -= 1;
+  }
+
+  len
+}
+
+// the length in bytes both texts end with, starting on a char boundary
+fn common_suffix(a: &str, b: &str) -> usize {
+  let mut len = a.bytes().rev().zip(b.bytes().rev()).take_while(|(x, y)| x == y).count();
+
+  while !a.is_char_boundary(a.len() - len) {
+    len -= 1;
+  }
+
+  len
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::Post;
+
+  fn pending_post() -> Post {
+    let mut post = Post::new();
+    post.add_text("I ate a salad for lunch today");
+    post.request_review();
+    post
+  }
+
+  #[test]
+  fn reject_with_leaves_a_reason() {
+    let mut post = pending_post();
+
+    post.reject_with("too short");
+    post.reject_with("still too short");
+
+    assert_eq!("Draft", post.state_name());
+    assert_eq!(1, post.open_comments().len());
+    assert_eq!("too short", post.open_comments()[0].body);
+    assert_eq!(None, post.open_comments()[0].anchor);
+  }
+
+  #[test]
+  fn comments_must_be_inside_the_content() {
+    let mut post = pending_post();
+
+    assert_eq!(Some(0), post.add_comment("alice", 8..13, "which salad?"));
+    assert_eq!(None, post.add_comment("alice", 20..40, "out of range"));
+    assert_eq!("salad", post.comments()[0].quote);
+  }
+
+  #[test]
+  fn resolved_comments_are_not_open() {
+    let mut post = pending_post();
+    post.add_comment("alice", 8..13, "which salad?");
+
+    assert!(post.resolve_comment(0));
+    assert!(!post.resolve_comment(0));
+    assert!(post.open_comments().is_empty());
+  }
+
+  #[test]
+  fn appending_keeps_the_anchors() {
+    let mut post = pending_post();
+    post.add_comment("alice", 8..13, "which salad?");
+    post.reject();
+
+    post.add_text(", it was great");
+
+    let comment = &post.open_comments()[0];
+    assert_eq!(Some(8..13), comment.anchor);
+    assert!(!comment.outdated);
+  }
+
+  #[test]
+  fn anchors_follow_the_text() {
+    let mut post = pending_post();
+    post.add_comment("alice", 24..29, "when?");
+    post.add_comment("alice", 8..13, "which salad?");
+    post.reject();
+
+    post.replace_text("Yesterday I ate a salad for lunch today");
+
+    let comments = post.open_comments();
+    assert_eq!(Some(34..39), comments[0].anchor);
+    assert_eq!(Some(18..23), comments[1].anchor);
+    assert_eq!("salad", &post.text()[18..23]);
+  }
+
+  #[test]
+  fn changed_text_makes_the_comment_outdated() {
+    let mut post = pending_post();
+    post.add_comment("alice", 8..13, "which salad?");
+    post.add_comment("bob", 0..1, "who?");
+    post.reject();
+
+    post.replace_text("I ate a soup for lunch today");
+
+    let comments = post.open_comments();
+    assert!(comments[0].outdated);
+    assert_eq!(Some(0..1), comments[1].anchor);
+    assert!(!comments[1].outdated);
+  }
+}
@@ -0,0 +1,273 @@
+/*
+  ** Renders the workflow known by a Registry as Graphviz DOT or as a
+  ** Mermaid state diagram. The arrows come from Registry::transitions,
+  ** so the diagram shows what the states actually do when an action is
+  ** called on them instead of what someone wrote down about them.
+  **
+  ** Actions that leave a post in the state it already was in are
+  ** hidden by default since every state has a few of those.
+  **
+  ** Registered states and actions can be named anything, so names are
+  ** quoted in DOT, and in Mermaid, where ids can't be quoted, every state
+  ** gets an id made of the safe characters of its name and is labelled
+  ** with the whole name.
+*/
+
+use std::collections::HashSet;
+
+use crate::registry::{Edge, Registry, INITIAL_STATE};
+
+pub struct Diagram {
+  states: Vec<&'static str>,
+  edges: Vec<Edge>,
+  loops: bool,
+}
+
+impl Diagram {
+  pub fn new(registry: &Registry) -> Diagram {
+    Diagram {
+      states: registry.states(),
+      edges: registry.transitions(),
+      loops: false,
+    }
+  }
+
+  // also draw the actions that return the state itself
+  pub fn with_loops(mut self, loops: bool) -> Diagram {
+    self.loops = loops;
+    self
+  }
+
+  fn edges(&self) -> impl Iterator<Item = &Edge> {
+    let loops = self.loops;
+    self.edges.iter().filter(move |e| loops || !e.is_loop())
+  }
+
+  pub fn to_dot(&self) -> String {
+    let mut out = String::from("digraph Post {\n");
+
+    out.push_str("  start [shape=point];\n");
+    for state in &self.states {
+      out.push_str(&format!("  {} [shape=box];\n", dot_quote(state)));
+    }
+
+    out.push_str(&format!("  start -> {};\n", dot_quote(INITIAL_STATE)));
+    for edge in self.edges() {
+      // loops are dashed so they don't look like a step of the workflow
+      let style = if edge.is_loop() { ", style=dashed" } else { "" };
+
+      out.push_str(&format!(
+        "  {} -> {} [label={}{}];\n",
+        dot_quote(edge.from), dot_quote(edge.to), dot_quote(&edge.action), style
+      ));
+    }
+
+    out.push_str("}\n");
+    out
+  }
+
+  pub fn to_mermaid(&self) -> String {
+    let mut out = String::from("stateDiagram-v2\n");
+    // edges can lead to states that aren't registered, they need an id too
+    let names = self.states.iter().copied()
+      .chain(Some(INITIAL_STATE))
+      .chain(self.edges().flat_map(|e| vec![e.from, e.to]));
+    let ids = MermaidIds::new(names);
+
+    // states whose id isn't their name are declared with their name as label
+    for (name, id) in &ids.ids {
+      if id != name {
+        out.push_str(&format!("  state \"{}\" as {}\n", mermaid_text(name).replace('"', "#quot;"), id));
+      }
+    }
+
+    out.push_str(&format!("  [*] --> {}\n", ids.get(INITIAL_STATE)));
+    for edge in self.edges() {
+      out.push_str(&format!("  {} --> {}: {}\n", ids.get(edge.from), ids.get(edge.to), mermaid_text(&edge.action)));
+    }
+
+    out
+  }
+}
+
+// any name works as a DOT id once it's quoted
+fn dot_quote(name: &str) -> String {
+  let mut quoted = String::from("\"");
+
+  for c in name.chars() {
+    match c {
+      '"' | '\\' => {
+        quoted.push('\\');
+        quoted.push(c);
+      },
+      '\n' => quoted.push_str("\\n"),
+      _ => quoted.push(c),
+    }
+  }
+
+  quoted.push('"');
+  quoted
+}
+
+// a label can't go over more than one line, and ; ends a statement
+fn mermaid_text(text: &str) -> String {
+  text.replace('\n', " ").replace(';', ",")
+}
+
+// the ids of the states in a Mermaid diagram
+struct MermaidIds {
+  ids: Vec<(&'static str, String)>,
+}
+
+impl MermaidIds {
+  fn new<I>(names: I) -> MermaidIds
+    where I: Iterator<Item = &'static str>
+  {
+    let mut taken = HashSet::new();
+    let mut ids: Vec<(&'static str, String)> = Vec::new();
+
+    for state in names {
+      if ids.iter().any(|(name, _)| *name == state) {
+        continue;
+      }
+
+      let safe: String = state
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '_' { c } else { '_' })
+        .collect();
+      let safe = if safe.is_empty() { String::from("_") } else { safe };
+
+      // names that only differ in their unsafe characters get a number
+      let mut id = safe.clone();
+      let mut n = 1;
+      while !taken.insert(id.clone()) {
+        n += 1;
+        id = format!("{}_{}", safe, n);
+      }
+
+      ids.push((state, id));
+    }
+
+    MermaidIds { ids }
+  }
+
+  // every state of the diagram was given an id in new
+  fn get(&self, state: &str) -> &str {
+    &self.ids.iter().find(|(name, _)| *name == state).unwrap().1
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn dot_output() {
+    let dot = Diagram::new(&Registry::new()).to_dot();
+
+    assert_eq!("digraph Post {
+  start [shape=point];
+  \"Draft\" [shape=box];
+  \"PendingReview\" [shape=box];
+  \"Published\" [shape=box];
+  start -> \"Draft\";
+  \"Draft\" -> \"PendingReview\" [label=\"request_review\"];
+  \"PendingReview\" -> \"Published\" [label=\"approve\"];
+  \"PendingReview\" -> \"Draft\" [label=\"reject\"];
+}
+", dot);
+  }
+
+  #[test]
+  fn mermaid_output() {
+    let mermaid = Diagram::new(&Registry::new()).to_mermaid();
+
+    assert_eq!("stateDiagram-v2
+  [*] --> Draft
+  Draft --> PendingReview: request_review
+  PendingReview --> Published: approve
+  PendingReview --> Draft: reject
+", mermaid);
+  }
+
+  struct Odd(&'static str);
+
+  impl crate::State for Odd {
+    fn name(&self) -> &'static str {
+      self.0
+    }
+
+    fn request_review(self: Box<Self>) -> Box<dyn crate::State> {
+      self
+    }
+
+    fn approve(self: Box<Self>, _reviewer: &str, _policy: &crate::ReviewPolicy) -> Box<dyn crate::State> {
+      self
+    }
+
+    fn reject(self: Box<Self>) -> Box<dyn crate::State> {
+      self
+    }
+  }
+
+  fn odd_registry() -> Registry {
+    let mut registry = Registry::new();
+    registry.register_state("On \"Hold\"", |_| Box::new(Odd("On \"Hold\"")));
+    registry.register_state("on-hold", |_| Box::new(Odd("on-hold")));
+    registry.register_action("put on hold", "Draft", |_, _| Box::new(Odd("On \"Hold\""))).unwrap();
+    registry.register_action("hold; later", "PendingReview", |_, _| Box::new(Odd("on-hold"))).unwrap();
+    registry
+  }
+
+  #[test]
+  fn dot_quotes_names() {
+    let dot = Diagram::new(&odd_registry()).to_dot();
+
+    assert!(dot.contains("  \"On \\\"Hold\\\"\" [shape=box];\n"));
+    assert!(dot.contains("  \"Draft\" -> \"On \\\"Hold\\\"\" [label=\"put on hold\"];\n"));
+    assert!(dot.contains("  \"PendingReview\" -> \"on-hold\" [label=\"hold; later\"];\n"));
+  }
+
+  #[test]
+  fn mermaid_ids_are_safe() {
+    let mermaid = Diagram::new(&odd_registry()).to_mermaid();
+
+    assert_eq!("stateDiagram-v2
+  state \"On #quot;Hold#quot;\" as On__Hold_
+  state \"on-hold\" as on_hold
+  [*] --> Draft
+  Draft --> PendingReview: request_review
+  Draft --> On__Hold_: put on hold
+  PendingReview --> Published: approve
+  PendingReview --> Draft: reject
+  PendingReview --> on_hold: hold, later
+", mermaid);
+  }
+
+  #[test]
+  fn clashing_ids_get_numbered() {
+    let ids = MermaidIds::new(vec!["a b", "a-b", "a_b"].into_iter());
+
+    assert_eq!("a_b", ids.get("a b"));
+    assert_eq!("a_b_2", ids.get("a-b"));
+    assert_eq!("a_b_3", ids.get("a_b"));
+  }
+
+  // the diagram in the README is the one the code draws
+  #[test]
+  fn readme_diagram_is_up_to_date() {
+    let readme = include_str!("../README.md");
+    let start = readme.find("```mermaid\n").expect("the README has no mermaid block") + "```mermaid\n".len();
+    let end = start + readme[start..].find("```").unwrap();
+
+    assert_eq!(Diagram::new(&Registry::new()).to_mermaid(), &readme[start..end]);
+  }
+
+  #[test]
+  fn loops_are_dashed() {
+    let dot = Diagram::new(&Registry::new()).with_loops(true).to_dot();
+
+    assert!(dot.contains("  \"Draft\" -> \"Draft\" [label=\"approve\", style=dashed];\n"));
+    assert!(dot.contains("  \"Draft\" -> \"PendingReview\" [label=\"request_review\"];\n"));
+  }
+}
@@ -0,0 +1,390 @@
+/*
+  ** A line based diff in the unified format used by `diff -u` and git.
+  ** The lines both texts have in common come from their longest common
+  ** subsequence, everything else is a line removed from the old text or
+  ** added by the new one. Changes are grouped in hunks with a few lines of
+  ** unchanged context around them.
+  **
+  ** Lines keep their line break, so texts that only differ in the newline
+  ** at the end, or in \r\n against \n, still have a diff. A last line
+  ** without a newline is marked the way `diff -u` does it.
+  **
+  ** The common subsequence is found with Myers' algorithm, in the version
+  ** that looks for the middle of the edit script from both ends at once and
+  ** splits the texts there. It only keeps two rows of numbers as long as the
+  ** texts, and takes time proportional to their length times the number of
+  ** lines that changed, so long texts with a few changes are cheap.
+*/
+
+// unchanged lines shown before and after each change
+const CONTEXT: usize = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Op {
+  Equal,
+  Delete,
+  Insert,
+}
+
+// returns an empty string when both texts have the same lines
+pub fn unified(old: &str, new: &str, old_name: &str, new_name: &str) -> String {
+  let a: Vec<&str> = old.split_inclusive('\n').collect();
+  let b: Vec<&str> = new.split_inclusive('\n').collect();
+
+  let ops = edit_script(&a, &b);
+  if ops.iter().all(|(op, _, _)| *op == Op::Equal) {
+    return String::new();
+  }
+
+  let mut out = format!("--- {}\n+++ {}\n", old_name, new_name);
+
+  for (start, end) in hunks(&ops) {
+    let hunk = &ops[start..end];
+
+    // the position of the hunk in each text is the number of lines
+    // of that text consumed before the first op of the hunk
+    let (a_pos, b_pos) = (hunk[0].1, hunk[0].2);
+    let a_len = hunk.iter().filter(|(op, _, _)| *op != Op::Insert).count();
+    let b_len = hunk.iter().filter(|(op, _, _)| *op != Op::Delete).count();
+
+    out.push_str(&format!("@@ -{} +{} @@\n", range(a_pos, a_len), range(b_pos, b_len)));
+
+    for (op, i, j) in hunk {
+      match op {
+        Op::Equal => push_line(&mut out, ' ', a[*i]),
+        Op::Delete => push_line(&mut out, '-', a[*i]),
+        Op::Insert => push_line(&mut out, '+', b[*j]),
+      }
+    }
+  }
+
+  out
+}
+
+// only the last line of a text can be missing its newline
+fn push_line(out: &mut String, sign: char, line: &str) {
+  out.push(sign);
+  out.push_str(line);
+
+  if !line.ends_with('\n') {
+    out.push_str("\n\\ No newline at end of file\n");
+  }
+}
+
+// ranges are 1-based and an empty range points at the line before it
+// a range of a single line is written without its length
+fn range(pos: usize, len: usize) -> String {
+  match len {
+    0 => format!("{},0", pos),
+    1 => format!("{}", pos + 1),
+    _ => format!("{},{}", pos + 1, len),
+  }
+}
+
+// each op carries the index of the current line in both texts
+fn edit_script(a: &[&str], b: &[&str]) -> Vec<(Op, usize, usize)> {
+  let mut ops = Vec::with_capacity(a.len().max(b.len()));
+  script_into(a, b, 0, 0, &mut ops);
+  ops
+}
+
+// appends the ops going from a to b, which start at line
+// a_pos of the old text and b_pos of the new one
+fn script_into(a: &[&str], b: &[&str], a_pos: usize, b_pos: usize, ops: &mut Vec<(Op, usize, usize)>) {
+  // an edit usually leaves most of the text alone, so the lines
+  // both texts start and end with are matched right away
+  let prefix = a.iter().zip(b).take_while(|(x, y)| x == y).count();
+  let suffix = a[prefix..].iter().rev()
+    .zip(b[prefix..].iter().rev())
+    .take_while(|(x, y)| x == y)
+    .count();
+
+  ops.extend((0..prefix).map(|k| (Op::Equal, a_pos + k, b_pos + k)));
+
+  let (a_mid, b_mid) = (&a[prefix..a.len() - suffix], &b[prefix..b.len() - suffix]);
+  let (a_pos, b_pos) = (a_pos + prefix, b_pos + prefix);
+
+  match middle(a_mid, b_mid) {
+    Some((x, y)) => {
+      script_into(&a_mid[..x], &b_mid[..y], a_pos, b_pos, ops);
+      script_into(&a_mid[x..], &b_mid[y..], a_pos + x, b_pos + y, ops);
+    },
+    // nothing in common, every line goes
+    None => {
+      ops.extend((0..a_mid.len()).map(|k| (Op::Delete, a_pos + k, b_pos)));
+      ops.extend((0..b_mid.len()).map(|k| (Op::Insert, a_pos + a_mid.len(), b_pos + k)));
+    },
+  }
+
+  let (a_end, b_end) = (a_pos + a_mid.len(), b_pos + b_mid.len());
+  ops.extend((0..suffix).map(|k| (Op::Equal, a_end + k, b_end + k)));
+}
+
+/*
+  ** Where a shortest edit script from a to b can be split in two, as the
+  ** lines (x, y) of a and b it passes through, or None when the texts have
+  ** no line in common.
+  **
+  ** An edit script is a path from the top left to the bottom right of a
+  ** grid with a along one side and b along the other: going right deletes a
+  ** line, going down inserts one and going diagonally keeps a line both
+  ** have. Paths are followed forwards from the start and backwards from the
+  ** end, one more edit at a time, and the first place where they meet is in
+  ** the middle of a shortest path. forward[k] is how far along a the
+  ** furthest forward path on diagonal k (x - y) got, backward[k] the same
+  ** for the paths going backwards, counting from the ends of the texts.
+*/
+fn middle(a: &[&str], b: &[&str]) -> Option<(usize, usize)> {
+  if a.is_empty() || b.is_empty() {
+    return None;
+  }
+
+  let (n, m) = (a.len() as isize, b.len() as isize);
+  let max = (n + m + 1) / 2;
+  // diagonals go from -max to max, shifted so they can index the rows
+  let offset = max;
+  let mut forward = vec![-1; 2 * max as usize + 2];
+  let mut backward = forward.clone();
+  forward[offset as usize + 1] = 0;
+  backward[offset as usize + 1] = 0;
+
+  // the paths meet while going forwards when the texts differ
+  // by an odd number of lines, and backwards otherwise
+  let delta = n - m;
+  let meet_forwards = delta % 2 != 0;
+  let at = |row: &[isize], k: isize| row[(offset + k) as usize];
+
+  // diagonals at the edges whose paths already ran off the grid
+  let (mut k1_start, mut k1_end, mut k2_start, mut k2_end) = (0, 0, 0, 0);
+
+  for d in 0..max {
+    let mut k1 = -d + k1_start;
+    while k1 <= d - k1_end {
+      let mut x = if k1 == -d || (k1 != d && at(&forward, k1 - 1) < at(&forward, k1 + 1)) {
+        at(&forward, k1 + 1)
+      } else {
+        at(&forward, k1 - 1) + 1
+      };
+      let mut y = x - k1;
+
+      while x < n && y < m && a[x as usize] == b[y as usize] {
+        x += 1;
+        y += 1;
+      }
+      forward[(offset + k1) as usize] = x;
+
+      if x > n {
+        k1_end += 2;
+      } else if y > m {
+        k1_start += 2;
+      } else if meet_forwards {
+        let k2 = delta - k1;
+        if (-max..=max).contains(&k2) && at(&backward, k2) != -1 && x >= n - at(&backward, k2) {
+          return Some((x as usize, y as usize));
+        }
+      }
+
+      k1 += 2;
+    }
+
+    let mut k2 = -d + k2_start;
+    while k2 <= d - k2_end {
+      let mut x = if k2 == -d || (k2 != d && at(&backward, k2 - 1) < at(&backward, k2 + 1)) {
+        at(&backward, k2 + 1)
+      } else {
+        at(&backward, k2 - 1) + 1
+      };
+      let mut y = x - k2;
+
+      while x < n && y < m && a[(n - x - 1) as usize] == b[(m - y - 1) as usize] {
+        x += 1;
+        y += 1;
+      }
+      backward[(offset + k2) as usize] = x;
+
+      if x > n {
+        k2_end += 2;
+      } else if y > m {
+        k2_start += 2;
+      } else if !meet_forwards {
+        let k1 = delta - k2;
+        if (-max..=max).contains(&k1) && at(&forward, k1) != -1 {
+          let x1 = at(&forward, k1);
+          if x1 >= n - x {
+            return Some((x1 as usize, (x1 - k1) as usize));
+          }
+        }
+      }
+
+      k2 += 2;
+    }
+  }
+
+  None
+}
+
+// the ranges of ops to print, changes that are close enough
+// for their context to touch end up in the same hunk
+fn hunks(ops: &[(Op, usize, usize)]) -> Vec<(usize, usize)> {
+  let mut hunks: Vec<(usize, usize)> = Vec::new();
+
+  for (k, (op, _, _)) in ops.iter().enumerate() {
+    if *op == Op::Equal {
+      continue;
+    }
+
+    let start = k.saturating_sub(CONTEXT);
+    let end = (k + 1 + CONTEXT).min(ops.len());
+
+    match hunks.last_mut() {
+      Some(last) if start <= last.1 => last.1 = end,
+      _ => hunks.push((start, end)),
+    }
+  }
+
+  hunks
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn same_text_has_no_diff() {
+    assert_eq!("", unified("a\nb\n", "a\nb\n", "a", "b"));
+  }
+
+  #[test]
+  fn changed_line() {
+    let diff = unified("one\ntwo\nthree\n", "one\n2\nthree\nfour\n", "revision 1", "revision 2");
+
+    assert_eq!("--- revision 1
++++ revision 2
+@@ -1,3 +1,4 @@
+ one
+-two
++2
+ three
++four
+", diff);
+  }
+
+  #[test]
+  fn distant_changes_get_their_own_hunks() {
+    let old = "1\n2\n3\n4\n5\n6\n7\n8\n9\n10\n";
+    let new = "one\n2\n3\n4\n5\n6\n7\n8\n9\n";
+
+    assert_eq!("--- a
++++ b
+@@ -1,4 +1,4 @@
+-1
++one
+ 2
+ 3
+ 4
+@@ -7,4 +7,3 @@
+ 7
+ 8
+ 9
+-10
+", unified(old, new, "a", "b"));
+  }
+
+  #[test]
+  fn long_texts_with_a_small_change() {
+    let old: String = (0..20_000).map(|n| format!("line {}\n", n)).collect();
+    let new = old.replacen("line 10000\n", "line ten thousand\n", 1);
+
+    assert_eq!("--- a
++++ b
+@@ -9998,7 +9998,7 @@
+ line 9997
+ line 9998
+ line 9999
+-line 10000
++line ten thousand
+ line 10001
+ line 10002
+ line 10003
+", unified(&old, &new, "a", "b"));
+  }
+
+  #[test]
+  fn from_empty_text() {
+    assert_eq!("--- a\n+++ b\n@@ -0,0 +1 @@\n+hello\n", unified("", "hello\n", "a", "b"));
+  }
+
+  #[test]
+  fn a_missing_newline_at_the_end_is_a_change() {
+    assert_eq!("--- a
++++ b
+@@ -1 +1 @@
+-a
+\\ No newline at end of file
++a
+", unified("a", "a\n", "a", "b"));
+
+    assert_eq!("--- a\n+++ b\n@@ -1 +1 @@\n-a\r\n+a\n", unified("a\r\n", "a\n", "a", "b"));
+  }
+
+  #[test]
+  fn long_texts_changed_at_both_ends() {
+    let lines: String = (0..10_000).map(|n| format!("line {}\n", n)).collect();
+    let old = format!("first\n{}last\n", lines);
+    let new = format!("1st\n{}end\n", lines);
+
+    let diff = unified(&old, &new, "a", "b");
+    assert!(diff.starts_with("--- a\n+++ b\n@@ -1,4 +1,4 @@\n-first\n+1st\n"));
+    assert!(diff.ends_with("@@ -9999,4 +9999,4 @@\n line 9997\n line 9998\n line 9999\n-last\n+end\n"));
+  }
+
+  // the length of the longest common subsequence, the slow way
+  fn lcs_len(a: &[&str], b: &[&str]) -> usize {
+    let mut lcs = vec![vec![0; b.len() + 1]; a.len() + 1];
+
+    for i in (0..a.len()).rev() {
+      for j in (0..b.len()).rev() {
+        lcs[i][j] = if a[i] == b[j] { lcs[i + 1][j + 1] + 1 } else { lcs[i + 1][j].max(lcs[i][j + 1]) };
+      }
+    }
+
+    lcs[0][0]
+  }
+
+  #[test]
+  fn edit_scripts_are_shortest() {
+    // a small linear congruential generator, so the texts are the same on every run
+    let mut seed = 7u32;
+    let mut text = |len: usize| -> Vec<&str> {
+      (0..len).map(|_| {
+        seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+        ["a", "b", "c", "d"][(seed >> 16) as usize % 4]
+      }).collect()
+    };
+
+    for round in 0..200 {
+      let a = text(round % 13);
+      let b = text(round % 7 + round % 5);
+      let ops = edit_script(&a, &b);
+
+      // the ops go through both texts in order
+      let (mut i, mut j) = (0, 0);
+      for (op, x, y) in &ops {
+        assert_eq!((i, j), (*x, *y));
+        match op {
+          Op::Equal => {
+            assert_eq!(a[i], b[j]);
+            i += 1;
+            j += 1;
+          },
+          Op::Delete => i += 1,
+          Op::Insert => j += 1,
+        }
+      }
+      assert_eq!((a.len(), b.len()), (i, j));
+
+      let kept = ops.iter().filter(|(op, _, _)| *op == Op::Equal).count();
+      assert_eq!(lcs_len(&a, &b), kept, "{:?} {:?}", a, b);
+    }
+  }
+}
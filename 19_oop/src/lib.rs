@@ -1,4 +1,4 @@
-/**
+/*
  * This is a implementation of the State Pattern from OOP
  * with Rust just to show how Rust can be adapted to this kind
  * of pattern but, this leads to a couple of problems and doesn't really
@@ -11,19 +11,110 @@
  * of its features like Ownership, which OOP does not take into account
  */
 
+use std::error::Error;
+use std::fmt;
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use serde::{Deserialize, Serialize};
+
+pub mod comments;
+pub mod diagram;
+pub mod diff;
+pub mod registry;
+pub mod snapshot;
+pub mod store;
+pub mod typed;
+
+use comments::Comment;
+use registry::Registry;
+
+// the reviewer used when approve is called without saying who is approving
+// all of those calls count as the same reviewer
+const ANONYMOUS_REVIEWER: &str = "anonymous";
+
+// how many different reviewers have to approve
+// a post before it gets published
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ReviewPolicy {
+  pub required_approvals: usize,
+}
+
+impl Default for ReviewPolicy {
+  // a single approval is enough to publish,
+  // which is how the workflow always worked
+  fn default() -> ReviewPolicy {
+    ReviewPolicy { required_approvals: 1 }
+  }
+}
+
+// a record of one action called on a post, e.g. request_review or approve
+// even calls that the state ignored are kept, with no_op set
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Transition {
+  pub from: String,
+  pub to: String,
+  pub action: String,
+  pub actor: Option<String>,
+  pub at: SystemTime,
+  pub no_op: bool,
+}
+
+// the name of a state, as returned by State::name
+pub type StateName = &'static str;
+
+// returned by the try_ methods when the current state ignored the action
+#[derive(Debug, Clone, PartialEq)]
+pub struct TransitionError {
+  pub state: StateName,
+  pub action: String,
+}
+
+impl fmt::Display for TransitionError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "can't {} a post in the {} state", self.action, self.state)
+  }
+}
+
+impl Error for TransitionError {}
+
 pub struct Post {
   state: Option<Box<dyn State>>,
   content: String,
+  // every version of the content, the last one is the current content
+  revisions: Vec<String>,
+  // the revision that was current the last time the post got published
+  published: Option<usize>,
+  comments: Vec<Comment>,
+  policy: ReviewPolicy,
+  history: Vec<Transition>,
+  // shared between posts since it is only read after it is set up
+  registry: Arc<Registry>,
 }
 
 impl Post {
   pub fn new() -> Post {
+    Post::with_policy(ReviewPolicy::default())
+  }
+
+  pub fn with_policy(policy: ReviewPolicy) -> Post {
+    Post::with_registry(registry::builtin(), policy)
+  }
+
+  // a post that can use the states and actions added to the registry
+  pub fn with_registry(registry: Arc<Registry>, policy: ReviewPolicy) -> Post {
     Post {
       // both private so code from the outside can't directly interact with it
       // also, by being private, we make sure that Posts will always
       // start with its state as a new Draft
       state: Some(Box::new(Draft {})),
       content: String::new(),
+      revisions: vec![String::new()],
+      published: None,
+      comments: Vec::new(),
+      policy,
+      history: Vec::new(),
+      registry,
     }
   }
 
@@ -34,7 +125,53 @@ impl Post {
     ** on the state pattern
   */
   pub fn add_text(&mut self, text: &str) {
-    self.content.push_str(text);
+    self.edit(|content| content.push_str(text));
+  }
+
+  pub fn replace_text(&mut self, text: &str) {
+    self.edit(|content| *content = text.to_string());
+  }
+
+  // len is in bytes, like String::truncate, but when it falls
+  // in the middle of a char that whole char is cut too
+  pub fn truncate(&mut self, len: usize) {
+    self.edit(|content| {
+      let mut len = len.min(content.len());
+
+      while !content.is_char_boundary(len) {
+        len -= 1;
+      }
+
+      content.truncate(len);
+    });
+  }
+
+  // revisions are numbered from 0, the empty post
+  // and every edit that changed the content adds one
+  pub fn revision(&self) -> usize {
+    self.revisions.len() - 1
+  }
+
+  pub fn revision_text(&self, revision: usize) -> Option<&str> {
+    self.revisions.get(revision).map(|r| r.as_str())
+  }
+
+  pub fn published_revision(&self) -> Option<usize> {
+    self.published
+  }
+
+  // a unified diff going from rev_a to rev_b,
+  // None when one of them doesn't exist
+  pub fn diff(&self, rev_a: usize, rev_b: usize) -> Option<String> {
+    let a = self.revision_text(rev_a)?;
+    let b = self.revision_text(rev_b)?;
+
+    Some(diff::unified(
+      a,
+      b,
+      &format!("revision {}", rev_a),
+      &format!("revision {}", rev_b),
+    ))
   }
 
   // if value is published, we want to return the value
@@ -47,55 +184,222 @@ impl Post {
     // This is one of the cases where we know more than the compiler
     // so we just call unwrap because we know Post will always have
     // Some value when those methods as done
-    self.state.as_ref().unwrap().content(&self)
+    self.state.as_ref().unwrap().content(self)
+  }
+
+  // the text written so far, no matter the state
+  // this is what states outside of this crate can use to show the content
+  pub fn text(&self) -> &str {
+    &self.content
   }
 
   pub fn request_review(&mut self) {
-    // we call the "take" method to take the Some value
-    // out of the state Option and leave a None in its place
-    // because Rust doesn't let us have unpopulated fields in structs
-    // this will let us move the value out of Post instead of borrowing it
-    if let Some(s) = self.state.take(){
-      // this method consumes the current state and returns a new state
-      self.state = Some(s.request_review())
-    }
+    // this method consumes the current state and returns a new state
+    self.transition("request_review", None, |s, _| s.request_review());
+  }
+
+  /*
+    ** The try_ methods do the same as the methods without the prefix,
+    ** but instead of silently doing nothing when the current state ignores
+    ** the action they return an error saying which state and action it was.
+    ** On success they return the name of the state the post is in now.
+    ** Ignored actions are still recorded in the history as no-ops.
+  */
+  pub fn try_request_review(&mut self) -> Result<StateName, TransitionError> {
+    let changed = self.transition("request_review", None, |s, _| s.request_review());
+    self.outcome("request_review", changed)
   }
 
   pub fn approve(&mut self) {
-    if let Some(s) = self.state.take() {
-      self.state = Some(s.approve())
-    }
+    self.approve_by(ANONYMOUS_REVIEWER)
+  }
+
+  // the state decides what an approval means, the post only
+  // tells it who is approving and which policy it follows
+  pub fn approve_by(&mut self, reviewer: &str) {
+    self.transition("approve", Some(reviewer), |s, policy| s.approve(reviewer, policy));
+  }
+
+  pub fn try_approve(&mut self) -> Result<StateName, TransitionError> {
+    self.try_approve_by(ANONYMOUS_REVIEWER)
+  }
+
+  // approving twice as the same reviewer is an error too,
+  // while an approval that doesn't publish the post yet is not
+  pub fn try_approve_by(&mut self, reviewer: &str) -> Result<StateName, TransitionError> {
+    let changed = self.transition("approve", Some(reviewer), |s, policy| s.approve(reviewer, policy));
+    self.outcome("approve", changed)
+  }
+
+  // the reviewers that approved the post in its current review
+  pub fn approvals(&self) -> &[String] {
+    self.state.as_ref().unwrap().approvals()
   }
 
   // extending the functionality is pretty easy
   // when using the state pattern
   pub fn reject(&mut self) {
+    self.transition("reject", None, |s, _| s.reject());
+  }
+
+  pub fn try_reject(&mut self) -> Result<StateName, TransitionError> {
+    let changed = self.transition("reject", None, |s, _| s.reject());
+    self.outcome("reject", changed)
+  }
+
+  // runs any action by its name, including the ones added to the registry
+  // for approve, the argument is the reviewer
+  // actions that the current state doesn't handle are recorded as no-ops
+  pub fn act(&mut self, action: &str, arg: Option<&str>) {
+    self.try_act(action, arg).ok();
+  }
+
+  pub fn try_act(&mut self, action: &str, arg: Option<&str>) -> Result<StateName, TransitionError> {
+    let registry = Arc::clone(&self.registry);
+    let actor = match action {
+      "approve" => Some(arg.unwrap_or(ANONYMOUS_REVIEWER)),
+      _ => None,
+    };
+
+    let changed = self.transition(action, actor, |s, policy| registry.apply(s, action, arg, policy));
+    self.outcome(action, changed)
+  }
+
+  pub fn state_name(&self) -> StateName {
+    self.state.as_ref().unwrap().name()
+  }
+
+  fn outcome(&self, action: &str, changed: bool) -> Result<StateName, TransitionError> {
+    if changed {
+      Ok(self.state_name())
+    } else {
+      Err(TransitionError {
+        state: self.state_name(),
+        action: action.to_string(),
+      })
+    }
+  }
+
+  // every transition the post went through, oldest first
+  pub fn history(&self) -> &[Transition] {
+    &self.history
+  }
+
+  fn published_content(&self) -> &str {
+    match self.published {
+      Some(revision) => &self.revisions[revision],
+      None => "",
+    }
+  }
+
+  // all edits go through here so each one becomes a revision
+  // editing a published post turns it back into a draft, but
+  // the published revision keeps being shown until it's approved again
+  // the same goes for a post some reviewers already approved, since
+  // they approved the text from before the edit
+  fn edit<F>(&mut self, f: F)
+    where F: FnOnce(&mut String)
+  {
+    let mut content = self.content.clone();
+    f(&mut content);
+
+    if content == self.content {
+      return;
+    }
+
+    let state = self.state.as_ref().unwrap();
+
+    if state.is_published() || !state.approvals().is_empty() {
+      self.transition("edit", None, |_, _| Box::new(Draft {}));
+    }
+
+    comments::reanchor(&mut self.comments, &self.content, &content);
+
+    self.content = content.clone();
+    self.revisions.push(content);
+  }
+
+  // all actions go through here so each one ends up in the history
+  // an action is a no-op when the state came back with the same
+  // name, approvals and payload it had before
+  // returns true when the action wasn't a no-op
+  fn transition<F>(&mut self, action: &str, actor: Option<&str>, f: F) -> bool
+    where F: FnOnce(Box<dyn State>, &ReviewPolicy) -> Box<dyn State>
+  {
+    // we call the "take" method to take the Some value
+    // out of the state Option and leave a None in its place
+    // because Rust doesn't let us have unpopulated fields in structs
+    // this will let us move the value out of Post instead of borrowing it
     if let Some(s) = self.state.take() {
-      self.state = Some(s.reject())
+      let from = s.name();
+      let approvals = s.approvals().len();
+      let payload = s.payload();
+
+      let next = f(s, &self.policy);
+      let no_op = next.name() == from
+        && next.approvals().len() == approvals
+        && next.payload() == payload;
+
+      if next.is_published() {
+        self.published = Some(self.revision());
+      }
+
+      self.history.push(Transition {
+        from: from.to_string(),
+        to: next.name().to_string(),
+        action: action.to_string(),
+        actor: actor.map(String::from),
+        at: SystemTime::now(),
+        no_op,
+      });
+
+      self.state = Some(next);
+
+      return !no_op;
     }
+
+    false
   }
  }
 
+impl Default for Post {
+  fn default() -> Post {
+    Post::new()
+  }
+}
+
 /* 
   ** The State trait will define the different
   ** Post states and the Draft.PendingReview and
   ** Draft.Published states will implement the State trait
+  **
+  ** It is public so other crates can add their own states,
+  ** which is why it has to stay object safe: no method returns Self
+  ** and none of them are generic, so it can always be a Box<dyn State>.
+  ** It also needs Send so posts can be moved across threads.
 */
-trait State {
+pub trait State: Send {
+  // the name used for this state in the post's history
+  // and to build it again from the registry
+  fn name(&self) -> &'static str;
+
   // now all types that implement State will need to 
   // implement the request_review method
   // Box<Self> means the method is only valid
   // when called on a Box holding the type
   // also, remember that this syntax is not using a reference
   // so it will take ownership and invalidate the old state value
+  // these can't have a default that returns self, since a
+  // Box<Self> can only become a Box<dyn State> when Self is sized
   fn request_review(self: Box<Self>) -> Box<dyn State>;
 
-  fn approve(self: Box<Self>) -> Box<dyn State>;
+  fn approve(self: Box<Self>, reviewer: &str, policy: &ReviewPolicy) -> Box<dyn State>;
 
   fn reject(self: Box<Self>) -> Box<dyn State>;
 
   // we add a default implementation for the content method
-  // that will return an empty slice. This default will be used for
+  // that will return the last published revision, which is an empty
+  // slice until the post gets published. This default will be used for
   // Draft and PendingReview states
   // we also need a lifetime annotation here because we're
   // taking a reference to a Post as an argument and returning
@@ -103,20 +407,41 @@ trait State {
   // returned reference is related to the lifetime of the
   // post argument
   fn content<'a>(&self, post: &'a Post) -> &'a str {
-    ""
+    post.published_content()
+  }
+
+  // a post entering a published state has its current revision
+  // recorded as the one to show while it's being edited again
+  fn is_published(&self) -> bool {
+    false
+  }
+
+  // only a post waiting for a review keeps track of approvals
+  fn approvals(&self) -> &[String] {
+    &[]
+  }
+
+  // any other data the state needs to be built again,
+  // e.g. the date a scheduled post will go out
+  fn payload(&self) -> Option<String> {
+    None
   }
 }
 
-struct Draft {}
+pub struct Draft {}
 
 impl State for Draft {
+  fn name(&self) -> &'static str {
+    "Draft"
+  }
+
   // Draft will need to return a new boxed instance of PendingReview
   // which represents the state when a post is waiting for a review
   fn request_review(self: Box<Self>) -> Box<dyn State> {
-    Box::new(PendingReview {})
+    Box::new(PendingReview { approvals: Vec::new() })
   }
 
-  fn approve(self: Box<Self>) -> Box<dyn State> {
+  fn approve(self: Box<Self>, _reviewer: &str, _policy: &ReviewPolicy) -> Box<dyn State> {
     self
   }
 
@@ -125,9 +450,18 @@ impl State for Draft {
   }
 }
 
-struct PendingReview {}
+// the state now carries data, each review starts
+// with no approvals and collects them until the policy is met
+#[derive(Default)]
+pub struct PendingReview {
+  approvals: Vec<String>,
+}
 
 impl State for PendingReview {
+  fn name(&self) -> &'static str {
+    "PendingReview"
+  }
+
   // PendingReview also implements State but it won't do anything
   // it returns itself because requesting a review on a PendingReview
   // should mainting its state
@@ -135,26 +469,48 @@ impl State for PendingReview {
     self
   }
 
-  fn approve(self: Box<Self>) -> Box<dyn State> {
-    Box::new(Published {})
+  // the same reviewer can't approve twice, so approving
+  // again just keeps the state as it is
+  fn approve(mut self: Box<Self>, reviewer: &str, policy: &ReviewPolicy) -> Box<dyn State> {
+    if self.approvals.iter().any(|r| r == reviewer) {
+      return self;
+    }
+
+    self.approvals.push(reviewer.to_string());
+
+    if self.approvals.len() >= policy.required_approvals {
+      Box::new(Published {})
+    } else {
+      self
+    }
   }
 
+  // going back to Draft drops the approvals collected so far
+  // so the next review has to start the tally again
   fn reject(self: Box<Self>) -> Box<dyn State> {
     Box::new(Draft {})
   }
+
+  fn approvals(&self) -> &[String] {
+    &self.approvals
+  }
 }
 
-struct Published {}
+pub struct Published {}
 
 // all method on Published should return itself
 // since the Published state should stay in its state
 // for both cases
 impl State for Published {
+  fn name(&self) -> &'static str {
+    "Published"
+  }
+
   fn request_review(self: Box<Self>) -> Box<dyn State> {
     self
   }
 
-  fn approve(self: Box<Self>) -> Box<dyn State> {
+  fn approve(self: Box<Self>, _reviewer: &str, _policy: &ReviewPolicy) -> Box<dyn State> {
     self
   }
 
@@ -165,4 +521,262 @@ impl State for Published {
   fn content<'a>(&self, post: &'a Post) -> &'a str {
     &post.content
   }
-}
\ No newline at end of file
+
+  fn is_published(&self) -> bool {
+    true
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn single_approval_publishes_by_default() {
+    let mut post = Post::new();
+    post.add_text("I ate a salad for lunch today");
+
+    post.request_review();
+    post.approve();
+
+    assert_eq!("I ate a salad for lunch today", post.content());
+  }
+
+  #[test]
+  fn policy_requires_different_reviewers() {
+    let mut post = Post::with_policy(ReviewPolicy { required_approvals: 2 });
+    post.add_text("I ate a salad for lunch today");
+
+    post.request_review();
+    post.approve_by("alice");
+    post.approve_by("alice");
+    assert_eq!("", post.content());
+    assert_eq!(["alice"], post.approvals());
+
+    post.approve_by("bob");
+    assert_eq!("I ate a salad for lunch today", post.content());
+  }
+
+  #[test]
+  fn reject_resets_the_approvals() {
+    let mut post = Post::with_policy(ReviewPolicy { required_approvals: 2 });
+    post.add_text("I ate a salad for lunch today");
+
+    post.request_review();
+    post.approve_by("alice");
+    post.reject();
+    assert!(post.approvals().is_empty());
+
+    post.request_review();
+    post.approve_by("bob");
+    assert_eq!("", post.content());
+
+    post.approve_by("alice");
+    assert_eq!("I ate a salad for lunch today", post.content());
+  }
+
+  #[test]
+  fn history_records_every_action() {
+    let mut post = Post::new();
+
+    post.approve();
+    post.request_review();
+    post.approve_by("alice");
+
+    let history = post.history();
+    assert_eq!(3, history.len());
+
+    assert_eq!("approve", history[0].action);
+    assert_eq!("Draft", history[0].from);
+    assert_eq!("Draft", history[0].to);
+    assert!(history[0].no_op);
+
+    assert_eq!("request_review", history[1].action);
+    assert_eq!("PendingReview", history[1].to);
+    assert_eq!(None, history[1].actor);
+    assert!(!history[1].no_op);
+
+    assert_eq!("Published", history[2].to);
+    assert_eq!(Some("alice".to_string()), history[2].actor);
+    assert!(!history[2].no_op);
+    assert!(history[1].at <= history[2].at);
+  }
+
+  #[test]
+  fn partial_approval_is_not_a_no_op() {
+    let mut post = Post::with_policy(ReviewPolicy { required_approvals: 2 });
+
+    post.request_review();
+    post.approve_by("alice");
+    post.approve_by("alice");
+
+    let history = post.history();
+    assert_eq!("PendingReview", history[1].to);
+    assert!(!history[1].no_op);
+    assert!(history[2].no_op);
+    assert_eq!("PendingReview", post.state_name());
+  }
+
+  struct Archived {}
+
+  impl State for Archived {
+    fn name(&self) -> &'static str {
+      "Archived"
+    }
+
+    fn request_review(self: Box<Self>) -> Box<dyn State> {
+      self
+    }
+
+    fn approve(self: Box<Self>, _reviewer: &str, _policy: &ReviewPolicy) -> Box<dyn State> {
+      self
+    }
+
+    fn reject(self: Box<Self>) -> Box<dyn State> {
+      self
+    }
+  }
+
+  #[test]
+  fn act_runs_registered_actions() {
+    let mut registry = Registry::new();
+    registry.register_state("Archived", |_| Box::new(Archived {}));
+    registry.register_action("archive", "Published", |_, _| Box::new(Archived {})).unwrap();
+
+    let mut post = Post::with_registry(Arc::new(registry), ReviewPolicy::default());
+    post.add_text("I ate a salad for lunch today");
+
+    post.act("archive", None);
+    assert_eq!("Draft", post.state_name());
+
+    post.act("request_review", None);
+    post.act("approve", Some("alice"));
+    assert_eq!("I ate a salad for lunch today", post.content());
+
+    post.act("archive", None);
+    assert_eq!("Archived", post.state_name());
+    // states that don't override content keep showing the last published revision
+    assert_eq!("I ate a salad for lunch today", post.content());
+    assert_eq!("I ate a salad for lunch today", post.text());
+
+    let history = post.history();
+    assert!(history[0].no_op);
+    assert_eq!(Some("alice".to_string()), history[2].actor);
+    assert_eq!("archive", history[3].action);
+    assert!(!history[3].no_op);
+  }
+
+  #[test]
+  fn edits_create_revisions() {
+    let mut post = Post::new();
+
+    post.add_text("I ate a salad");
+    post.add_text(" for lunch today");
+    post.add_text("");
+    post.truncate(13);
+    post.replace_text("I ate a salad\nand a soup");
+
+    assert_eq!(4, post.revision());
+    assert_eq!(Some(""), post.revision_text(0));
+    assert_eq!(Some("I ate a salad for lunch today"), post.revision_text(2));
+    assert_eq!(Some("I ate a salad"), post.revision_text(3));
+    assert_eq!(None, post.revision_text(5));
+
+    assert_eq!(
+      Some(String::from("--- revision 2\n+++ revision 4\n@@ -1 +1,2 @@\n\
+                         -I ate a salad for lunch today\n\\ No newline at end of file\n\
+                         +I ate a salad\n+and a soup\n\\ No newline at end of file\n")),
+      post.diff(2, 4)
+    );
+    assert_eq!(None, post.diff(2, 5));
+  }
+
+  #[test]
+  fn editing_a_published_post_starts_a_new_draft() {
+    let mut post = Post::new();
+    post.add_text("I ate a salad for lunch today");
+    post.request_review();
+    post.approve();
+    assert_eq!(Some(1), post.published_revision());
+
+    post.replace_text("I ate a soup for lunch today");
+
+    assert_eq!("Draft", post.state_name());
+    assert_eq!("I ate a salad for lunch today", post.content());
+    assert_eq!("I ate a soup for lunch today", post.text());
+    assert_eq!("edit", post.history().last().unwrap().action);
+
+    post.request_review();
+    assert_eq!("I ate a salad for lunch today", post.content());
+
+    post.approve();
+    assert_eq!("I ate a soup for lunch today", post.content());
+    assert_eq!(Some(2), post.published_revision());
+  }
+
+  #[test]
+  fn truncate_stops_at_a_char_boundary() {
+    let mut post = Post::new();
+    post.add_text("café au lait");
+
+    // byte 4 is in the middle of the é
+    post.truncate(4);
+    assert_eq!("caf", post.text());
+
+    post.truncate(100);
+    assert_eq!("caf", post.text());
+    assert_eq!(2, post.revision());
+  }
+
+  #[test]
+  fn editing_drops_the_approvals() {
+    let mut post = Post::with_policy(ReviewPolicy { required_approvals: 2 });
+    post.add_text("I ate a salad for lunch today");
+    post.request_review();
+    post.approve_by("alice");
+
+    post.replace_text("I ate a soup for lunch today");
+    assert_eq!("Draft", post.state_name());
+    assert!(post.approvals().is_empty());
+
+    // alice has to look at the new text too
+    post.request_review();
+    post.approve_by("bob");
+    assert_eq!("PendingReview", post.state_name());
+    post.approve_by("alice");
+    assert_eq!("I ate a soup for lunch today", post.content());
+  }
+
+  #[test]
+  fn editing_before_any_approval_keeps_the_review() {
+    let mut post = Post::new();
+    post.request_review();
+
+    post.add_text("I ate a salad for lunch today");
+
+    assert_eq!("PendingReview", post.state_name());
+  }
+
+  #[test]
+  fn try_methods_report_ignored_actions() {
+    let mut post = Post::with_policy(ReviewPolicy { required_approvals: 2 });
+
+    assert_eq!(
+      Err(TransitionError { state: "Draft", action: String::from("approve") }),
+      post.try_approve()
+    );
+    assert_eq!(Ok("PendingReview"), post.try_request_review());
+    assert!(post.try_request_review().is_err());
+
+    assert_eq!(Ok("PendingReview"), post.try_approve_by("alice"));
+    assert!(post.try_approve_by("alice").is_err());
+    assert_eq!(Ok("Published"), post.try_approve_by("bob"));
+
+    let err = post.try_reject().unwrap_err();
+    assert_eq!("can't reject a post in the Published state", err.to_string());
+    assert!(post.try_act("archive", None).is_err());
+
+    assert_eq!(8, post.history().len());
+    assert_eq!(5, post.history().iter().filter(|t| t.no_op).count());
+  }
+}
@@ -23,7 +23,8 @@
               can't accidentally be published.
 */
 
-use oop::Post;
+use oop::{Post, ReviewPolicy};
+use oop::typed::DraftPost;
 
 fn main() {
   // this will create a new draft blog post
@@ -53,4 +54,29 @@ fn main() {
 
   another_post.reject();
   assert_eq!("", another_post.content());
+
+  // this post needs two different reviewers to approve it
+  // the same reviewer approving twice only counts once
+  let mut reviewed_post = Post::with_policy(ReviewPolicy { required_approvals: 2 });
+
+  reviewed_post.add_text("I ate a salad for lunch today");
+  reviewed_post.request_review();
+
+  reviewed_post.approve_by("alice");
+  reviewed_post.approve_by("alice");
+  assert_eq!("", reviewed_post.content());
+
+  reviewed_post.approve_by("bob");
+  assert_eq!("I ate a salad for lunch today", reviewed_post.content());
+
+  // the same workflow, but with the states as types
+  // here each transition gives us back a different type
+  // so calling content on a draft wouldn't even compile
+  let mut typed_post = DraftPost::new();
+
+  typed_post.add_text("I ate a salad for lunch today");
+
+  let typed_post = typed_post.request_review();
+  let typed_post = typed_post.approve();
+  assert_eq!("I ate a salad for lunch today", typed_post.content());
 }
@@ -0,0 +1,343 @@
+/*
+  ** The Registry is what makes the workflow extensible from outside
+  ** of this crate. It knows how to build every state by its name, which
+  ** is needed to restore a Post from a snapshot, and it holds the handlers
+  ** for actions other than request_review, approve and reject.
+  **
+  ** Actions are registered for the state they start from, so an action like
+  ** "archive" can be added to the built-in Published state without having
+  ** to change its impl block.
+*/
+
+use std::collections::HashSet;
+use std::error::Error;
+use std::fmt;
+use std::sync::{Arc, OnceLock};
+
+use crate::{Draft, PendingReview, Published, ReviewPolicy, State, ANONYMOUS_REVIEWER};
+
+// the actions every state responds to through the State trait
+pub const BUILTIN_ACTIONS: [&str; 3] = ["request_review", "approve", "reject"];
+
+// the state every post starts in
+pub const INITIAL_STATE: &str = "Draft";
+
+// the parts of a state that can't be recovered from its name alone
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct StateData {
+  pub approvals: Vec<String>,
+  pub payload: Option<String>,
+}
+
+pub type StateFactory = Box<dyn Fn(&StateData) -> Box<dyn State> + Send + Sync>;
+
+// receives the current state and the argument passed to Post::act
+pub type ActionHandler = Box<dyn Fn(Box<dyn State>, Option<&str>) -> Box<dyn State> + Send + Sync>;
+
+// one arrow of the workflow: applying action on a post in
+// state "from" leaves it in state "to"
+#[derive(Debug, Clone, PartialEq)]
+pub struct Edge {
+  pub from: &'static str,
+  pub action: String,
+  pub to: &'static str,
+}
+
+impl Edge {
+  // the action returned the state it was called on
+  pub fn is_loop(&self) -> bool {
+    self.from == self.to
+  }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum GraphProblem {
+  // an action leads to a state that can't be built by name
+  UnknownTarget(Edge),
+  // no sequence of actions leads from a new post to this state
+  Unreachable(&'static str),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum RegistryError {
+  // request_review, approve and reject always go through the State trait,
+  // so a handler registered for them would never run
+  BuiltinAction(String),
+}
+
+impl fmt::Display for RegistryError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      RegistryError::BuiltinAction(action) => write!(f, "{} is a built-in action and can't be registered", action),
+    }
+  }
+}
+
+impl Error for RegistryError {}
+
+pub struct Registry {
+  // kept in the order they were registered
+  // so the transition graph always comes out the same
+  states: Vec<(&'static str, StateFactory)>,
+  actions: Vec<(String, &'static str, ActionHandler)>,
+}
+
+impl Registry {
+  // a registry that already knows the built-in states
+  pub fn new() -> Registry {
+    let mut registry = Registry {
+      states: Vec::new(),
+      actions: Vec::new(),
+    };
+
+    registry.register_state("Draft", |_| Box::new(Draft {}));
+    registry.register_state("PendingReview", |data| {
+      Box::new(PendingReview { approvals: data.approvals.clone() })
+    });
+    registry.register_state("Published", |_| Box::new(Published {}));
+
+    registry
+  }
+
+  // registering a name again replaces the factory for it
+  pub fn register_state<F>(&mut self, name: &'static str, factory: F)
+    where F: Fn(&StateData) -> Box<dyn State> + Send + Sync + 'static
+  {
+    match self.states.iter_mut().find(|(n, _)| *n == name) {
+      Some(entry) => entry.1 = Box::new(factory),
+      None => self.states.push((name, Box::new(factory))),
+    }
+  }
+
+  // the built-in actions are handled by the State trait itself
+  // so registering a handler for them is an error
+  pub fn register_action<F>(&mut self, action: &str, from: &'static str, handler: F) -> Result<(), RegistryError>
+    where F: Fn(Box<dyn State>, Option<&str>) -> Box<dyn State> + Send + Sync + 'static
+  {
+    if BUILTIN_ACTIONS.contains(&action) {
+      return Err(RegistryError::BuiltinAction(action.to_string()));
+    }
+
+    match self.actions.iter_mut().find(|(a, f, _)| a == action && *f == from) {
+      Some(entry) => entry.2 = Box::new(handler),
+      None => self.actions.push((action.to_string(), from, Box::new(handler))),
+    }
+
+    Ok(())
+  }
+
+  pub fn create(&self, name: &str, data: &StateData) -> Option<Box<dyn State>> {
+    self.states
+      .iter()
+      .find(|(n, _)| *n == name)
+      .map(|(_, factory)| factory(data))
+  }
+
+  pub fn states(&self) -> Vec<&'static str> {
+    self.states.iter().map(|(name, _)| *name).collect()
+  }
+
+  // the built-in actions first and then every registered one
+  pub fn actions(&self) -> Vec<&str> {
+    let mut actions: Vec<&str> = BUILTIN_ACTIONS.to_vec();
+
+    for (action, _, _) in &self.actions {
+      if !actions.contains(&action.as_str()) {
+        actions.push(action);
+      }
+    }
+
+    actions
+  }
+
+  // runs an action against a state, falling back to returning
+  // the state untouched when nothing handles that action
+  pub(crate) fn apply(
+    &self,
+    state: Box<dyn State>,
+    action: &str,
+    arg: Option<&str>,
+    policy: &ReviewPolicy,
+  ) -> Box<dyn State> {
+    match action {
+      "request_review" => state.request_review(),
+      "approve" => state.approve(arg.unwrap_or(ANONYMOUS_REVIEWER), policy),
+      "reject" => state.reject(),
+      _ => {
+        let from = state.name();
+
+        match self.actions.iter().find(|(a, f, _)| a == action && *f == from) {
+          Some((_, _, handler)) => handler(state, arg),
+          None => state,
+        }
+      },
+    }
+  }
+
+  /*
+    ** The graph is built by actually running the states instead of
+    ** reading a description of them: every registered state is created
+    ** with empty data and every known action is applied to it once,
+    ** with no argument and the default review policy.
+    ** Actions that make the state return itself show up as loops.
+  */
+  pub fn transitions(&self) -> Vec<Edge> {
+    let policy = ReviewPolicy::default();
+    let mut edges = Vec::new();
+
+    for (from, factory) in &self.states {
+      for action in self.actions() {
+        let state = factory(&StateData::default());
+        let to = self.apply(state, action, None, &policy).name();
+
+        edges.push(Edge {
+          from,
+          action: action.to_string(),
+          to,
+        });
+      }
+    }
+
+    edges
+  }
+
+  pub fn validate(&self) -> Vec<GraphProblem> {
+    let edges = self.transitions();
+    let states = self.states();
+    let mut problems = Vec::new();
+
+    for edge in &edges {
+      if !states.contains(&edge.to) {
+        problems.push(GraphProblem::UnknownTarget(edge.clone()));
+      }
+    }
+
+    // walk the edges starting from the state every post begins with
+    let mut reached = HashSet::new();
+    let mut pending = vec![INITIAL_STATE];
+
+    while let Some(state) = pending.pop() {
+      if reached.insert(state) {
+        for edge in edges.iter().filter(|e| e.from == state) {
+          pending.push(edge.to);
+        }
+      }
+    }
+
+    for state in states {
+      if !reached.contains(state) {
+        problems.push(GraphProblem::Unreachable(state));
+      }
+    }
+
+    problems
+  }
+}
+
+impl Default for Registry {
+  fn default() -> Registry {
+    Registry::new()
+  }
+}
+
+// the registry with only the built-in states, built the first time
+// it's needed and then shared by every post that wasn't given another one
+pub(crate) fn builtin() -> Arc<Registry> {
+  static BUILTIN: OnceLock<Arc<Registry>> = OnceLock::new();
+
+  Arc::clone(BUILTIN.get_or_init(|| Arc::new(Registry::new())))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  struct Archived {}
+
+  impl State for Archived {
+    fn name(&self) -> &'static str {
+      "Archived"
+    }
+
+    fn request_review(self: Box<Self>) -> Box<dyn State> {
+      self
+    }
+
+    fn approve(self: Box<Self>, _reviewer: &str, _policy: &ReviewPolicy) -> Box<dyn State> {
+      self
+    }
+
+    fn reject(self: Box<Self>) -> Box<dyn State> {
+      self
+    }
+  }
+
+  #[test]
+  fn builtin_graph() {
+    let edges = Registry::new().transitions();
+
+    let moves: Vec<(&str, &str, &str)> = edges
+      .iter()
+      .filter(|e| !e.is_loop())
+      .map(|e| (e.from, e.action.as_str(), e.to))
+      .collect();
+
+    assert_eq!(9, edges.len());
+    assert_eq!(vec![
+      ("Draft", "request_review", "PendingReview"),
+      ("PendingReview", "approve", "Published"),
+      ("PendingReview", "reject", "Draft"),
+    ], moves);
+  }
+
+  #[test]
+  fn registered_actions_are_part_of_the_graph() {
+    let mut registry = Registry::new();
+    registry.register_state("Archived", |_| Box::new(Archived {}));
+    registry.register_action("archive", "Published", |_, _| Box::new(Archived {})).unwrap();
+
+    assert_eq!(vec!["request_review", "approve", "reject", "archive"], registry.actions());
+    assert!(registry.transitions().contains(&Edge {
+      from: "Published",
+      action: String::from("archive"),
+      to: "Archived",
+    }));
+    assert!(registry.validate().is_empty());
+  }
+
+  #[test]
+  fn validate_finds_unknown_targets() {
+    let mut registry = Registry::new();
+    registry.register_action("archive", "Published", |_, _| Box::new(Archived {})).unwrap();
+
+    assert_eq!(vec![GraphProblem::UnknownTarget(Edge {
+      from: "Published",
+      action: String::from("archive"),
+      to: "Archived",
+    })], registry.validate());
+  }
+
+  #[test]
+  fn builtin_actions_cannot_be_registered() {
+    let mut registry = Registry::new();
+
+    assert_eq!(
+      Err(RegistryError::BuiltinAction(String::from("approve"))),
+      registry.register_action("approve", "Draft", |_, _| Box::new(Archived {}))
+    );
+    assert_eq!(vec!["request_review", "approve", "reject"], registry.actions());
+  }
+
+  #[test]
+  fn posts_share_the_builtin_registry() {
+    assert!(Arc::ptr_eq(&builtin(), &builtin()));
+  }
+
+  #[test]
+  fn validate_finds_unreachable_states() {
+    let mut registry = Registry::new();
+    registry.register_state("Archived", |_| Box::new(Archived {}));
+
+    assert_eq!(vec![GraphProblem::Unreachable("Archived")], registry.validate());
+  }
+}
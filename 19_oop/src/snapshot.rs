@@ -0,0 +1,749 @@
+/*
+  ** A Snapshot is everything needed to bring a Post back
+  ** in the same point of its workflow: the content and its revisions,
+  ** the name of the current state, the approvals of an ongoing review,
+  ** the policy, the history of transitions and the review comments.
+  **
+  ** It can be written as JSON or in a compact binary form.
+  ** Both formats carry a version number so the layout can change
+  ** later on without silently misreading older snapshots.
+*/
+
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fmt;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use crate::comments::Comment;
+use crate::registry::{self, Registry, StateData};
+use crate::{Post, ReviewPolicy, Transition};
+
+pub const SNAPSHOT_VERSION: u8 = 1;
+
+// the first bytes of every binary snapshot
+const MAGIC: &[u8; 4] = b"POST";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Snapshot {
+  pub version: u8,
+  pub content: String,
+  pub state: String,
+  pub approvals: Vec<String>,
+  pub payload: Option<String>,
+  pub required_approvals: usize,
+  pub history: Vec<Transition>,
+  pub revisions: Vec<String>,
+  pub published: Option<usize>,
+  pub comments: Vec<Comment>,
+}
+
+#[derive(Debug)]
+pub enum SnapshotError {
+  // the snapshot names a state this crate doesn't know about
+  UnknownState(String),
+  UnsupportedVersion(u8),
+  Json(serde_json::Error),
+  // the binary snapshot is truncated or doesn't follow the layout
+  Malformed(&'static str),
+  // a length or number doesn't fit in the u32 the binary layout has for it
+  TooLarge(&'static str),
+}
+
+impl fmt::Display for SnapshotError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      SnapshotError::UnknownState(name) => write!(f, "unknown post state: {}", name),
+      SnapshotError::UnsupportedVersion(v) => write!(f, "unsupported snapshot version: {}", v),
+      SnapshotError::Json(e) => write!(f, "invalid snapshot json: {}", e),
+      SnapshotError::Malformed(reason) => write!(f, "malformed snapshot: {}", reason),
+      SnapshotError::TooLarge(what) => write!(f, "snapshot too large: {} doesn't fit in 32 bits", what),
+    }
+  }
+}
+
+impl Error for SnapshotError {
+  fn source(&self) -> Option<&(dyn Error + 'static)> {
+    match self {
+      SnapshotError::Json(e) => Some(e),
+      _ => None,
+    }
+  }
+}
+
+impl From<serde_json::Error> for SnapshotError {
+  fn from(e: serde_json::Error) -> SnapshotError {
+    SnapshotError::Json(e)
+  }
+}
+
+impl Snapshot {
+  pub fn to_json(&self) -> String {
+    // a snapshot only holds strings, numbers and lists
+    // so turning it into json can't fail
+    serde_json::to_string(self).unwrap()
+  }
+
+  pub fn from_json(json: &str) -> Result<Snapshot, SnapshotError> {
+    let snapshot: Snapshot = serde_json::from_str(json)?;
+    check_version(snapshot.version)?;
+
+    Ok(snapshot)
+  }
+
+  /*
+    ** The binary layout is the magic bytes, the version and then
+    ** every field in the order they are declared.
+    ** Strings and lists are prefixed by their length as a little endian u32,
+    ** timestamps are the seconds(u64) and nanoseconds(u32) since the unix epoch.
+    ** Optional values are a 0 or 1 byte followed by the value when it's 1.
+  */
+  pub fn to_bytes(&self) -> Result<Vec<u8>, SnapshotError> {
+    let mut buf = Vec::new();
+
+    buf.extend_from_slice(MAGIC);
+    buf.push(self.version);
+
+    write_str(&mut buf, &self.content)?;
+    write_str(&mut buf, &self.state)?;
+
+    write_len(&mut buf, self.approvals.len(), "approvals")?;
+    for reviewer in &self.approvals {
+      write_str(&mut buf, reviewer)?;
+    }
+
+    write_opt_str(&mut buf, self.payload.as_deref())?;
+
+    write_len(&mut buf, self.required_approvals, "required approvals")?;
+
+    write_len(&mut buf, self.history.len(), "history")?;
+    for t in &self.history {
+      write_str(&mut buf, &t.from)?;
+      write_str(&mut buf, &t.to)?;
+      write_str(&mut buf, &t.action)?;
+
+      write_opt_str(&mut buf, t.actor.as_deref())?;
+
+      // transitions are never recorded before the epoch
+      let since_epoch = t.at.duration_since(UNIX_EPOCH).unwrap_or_default();
+      buf.extend_from_slice(&since_epoch.as_secs().to_le_bytes());
+      write_u32(&mut buf, since_epoch.subsec_nanos());
+
+      buf.push(t.no_op as u8);
+    }
+
+    write_len(&mut buf, self.revisions.len(), "revisions")?;
+    for revision in &self.revisions {
+      write_str(&mut buf, revision)?;
+    }
+
+    match self.published {
+      Some(revision) => {
+        buf.push(1);
+        write_len(&mut buf, revision, "published revision")?;
+      },
+      None => buf.push(0),
+    }
+
+    write_len(&mut buf, self.comments.len(), "comments")?;
+    for c in &self.comments {
+      write_len(&mut buf, c.id, "comment id")?;
+      write_opt_str(&mut buf, c.author.as_deref())?;
+      write_str(&mut buf, &c.body)?;
+
+      match &c.anchor {
+        Some(range) => {
+          buf.push(1);
+          write_len(&mut buf, range.start, "comment anchor")?;
+          write_len(&mut buf, range.end, "comment anchor")?;
+        },
+        None => buf.push(0),
+      }
+
+      write_str(&mut buf, &c.quote)?;
+      write_len(&mut buf, c.revision, "comment revision")?;
+      buf.push(c.outdated as u8);
+      buf.push(c.resolved as u8);
+    }
+
+    Ok(buf)
+  }
+
+  pub fn from_bytes(bytes: &[u8]) -> Result<Snapshot, SnapshotError> {
+    let mut r = Reader { bytes };
+
+    if r.take(MAGIC.len())? != MAGIC {
+      return Err(SnapshotError::Malformed("missing magic bytes"));
+    }
+
+    let version = r.u8()?;
+    check_version(version)?;
+
+    let content = r.string()?;
+    let state = r.string()?;
+
+    let mut approvals = Vec::new();
+    for _ in 0..r.u32()? {
+      approvals.push(r.string()?);
+    }
+
+    let payload = r.opt_string()?;
+
+    let required_approvals = r.u32()? as usize;
+
+    let mut history = Vec::new();
+    for _ in 0..r.u32()? {
+      let from = r.string()?;
+      let to = r.string()?;
+      let action = r.string()?;
+
+      let actor = r.opt_string()?;
+
+      let at = r.timestamp()?;
+
+      let no_op = r.bool()?;
+
+      history.push(Transition { from, to, action, actor, at, no_op });
+    }
+
+    let mut revisions = Vec::new();
+    for _ in 0..r.u32()? {
+      revisions.push(r.string()?);
+    }
+
+    let published = match r.u8()? {
+      0 => None,
+      1 => Some(r.u32()? as usize),
+      _ => return Err(SnapshotError::Malformed("invalid published flag")),
+    };
+
+    let mut comments = Vec::new();
+    for _ in 0..r.u32()? {
+      let id = r.u32()? as usize;
+      let author = r.opt_string()?;
+      let body = r.string()?;
+
+      let anchor = match r.u8()? {
+        0 => None,
+        1 => Some(r.u32()? as usize..r.u32()? as usize),
+        _ => return Err(SnapshotError::Malformed("invalid anchor flag")),
+      };
+
+      let quote = r.string()?;
+      let revision = r.u32()? as usize;
+      let outdated = r.bool()?;
+      let resolved = r.bool()?;
+
+      comments.push(Comment { id, author, body, anchor, quote, revision, outdated, resolved });
+    }
+
+    if !r.bytes.is_empty() {
+      return Err(SnapshotError::Malformed("trailing bytes"));
+    }
+
+    Ok(Snapshot {
+      version,
+      content,
+      state,
+      approvals,
+      payload,
+      required_approvals,
+      history,
+      revisions,
+      published,
+      comments,
+    })
+  }
+}
+
+fn check_version(version: u8) -> Result<(), SnapshotError> {
+  if version != SNAPSHOT_VERSION {
+    return Err(SnapshotError::UnsupportedVersion(version));
+  }
+
+  Ok(())
+}
+
+// comments are numbered in the order they were made, and the open ones
+// that aren't outdated point at their quote in the current content
+fn check_comments(comments: &[Comment], content: &str, revisions: usize) -> Result<(), SnapshotError> {
+  for (i, c) in comments.iter().enumerate() {
+    if c.id != i {
+      return Err(SnapshotError::Malformed("comment ids are out of order"));
+    }
+
+    if c.revision >= revisions {
+      return Err(SnapshotError::Malformed("comment revision doesn't exist"));
+    }
+
+    // the anchors of the other comments stopped following the content
+    if c.resolved || c.outdated {
+      continue;
+    }
+
+    if let Some(anchor) = &c.anchor {
+      // get is None when the range is out of the content or splits a char
+      match content.get(anchor.clone()) {
+        Some(quote) if quote == c.quote => {},
+        Some(_) => return Err(SnapshotError::Malformed("comment anchor doesn't point at its quote")),
+        None => return Err(SnapshotError::Malformed("comment anchor is outside of the content")),
+      }
+    }
+  }
+
+  Ok(())
+}
+
+impl Post {
+  pub fn snapshot(&self) -> Snapshot {
+    Snapshot {
+      version: SNAPSHOT_VERSION,
+      content: self.content.clone(),
+      state: self.state_name().to_string(),
+      approvals: self.approvals().to_vec(),
+      payload: self.state.as_ref().unwrap().payload(),
+      required_approvals: self.policy.required_approvals,
+      history: self.history.clone(),
+      revisions: self.revisions.clone(),
+      published: self.published,
+      comments: self.comments.clone(),
+    }
+  }
+
+  pub fn from_snapshot(snapshot: Snapshot) -> Result<Post, SnapshotError> {
+    Post::from_snapshot_with(snapshot, registry::builtin())
+  }
+
+  // the state is rebuilt from its name through the registry, so a snapshot
+  // can't smuggle in a state that the workflow doesn't have
+  pub fn from_snapshot_with(snapshot: Snapshot, registry: Arc<Registry>) -> Result<Post, SnapshotError> {
+    let data = StateData {
+      approvals: snapshot.approvals,
+      payload: snapshot.payload,
+    };
+
+    let state = match registry.create(&snapshot.state, &data) {
+      Some(state) => state,
+      None => return Err(SnapshotError::UnknownState(snapshot.state)),
+    };
+
+    let revisions = snapshot.revisions;
+    let published = snapshot.published;
+
+    if revisions.last() != Some(&snapshot.content) {
+      return Err(SnapshotError::Malformed("content is not the last revision"));
+    }
+
+    if published.is_some_and(|p| p >= revisions.len()) {
+      return Err(SnapshotError::Malformed("published revision doesn't exist"));
+    }
+
+    check_comments(&snapshot.comments, &snapshot.content, revisions.len())?;
+
+    Ok(Post {
+      state: Some(state),
+      content: snapshot.content,
+      revisions,
+      published,
+      comments: snapshot.comments,
+      policy: ReviewPolicy { required_approvals: snapshot.required_approvals },
+      history: snapshot.history,
+      registry,
+    })
+  }
+
+  // a copy of the post made through a snapshot, since states can't be
+  // cloned. It fails when the post is in a state its registry can't build
+  pub(crate) fn duplicate(&self) -> Result<Post, SnapshotError> {
+    Post::from_snapshot_with(self.snapshot(), Arc::clone(&self.registry))
+  }
+}
+
+fn write_u32(buf: &mut Vec<u8>, n: u32) {
+  buf.extend_from_slice(&n.to_le_bytes());
+}
+
+// lengths, ids and positions are usize in memory but a u32 in the layout
+fn write_len(buf: &mut Vec<u8>, n: usize, what: &'static str) -> Result<(), SnapshotError> {
+  let n = u32::try_from(n).map_err(|_| SnapshotError::TooLarge(what))?;
+  write_u32(buf, n);
+  Ok(())
+}
+
+fn write_str(buf: &mut Vec<u8>, s: &str) -> Result<(), SnapshotError> {
+  write_len(buf, s.len(), "string")?;
+  buf.extend_from_slice(s.as_bytes());
+  Ok(())
+}
+
+fn write_opt_str(buf: &mut Vec<u8>, s: Option<&str>) -> Result<(), SnapshotError> {
+  match s {
+    Some(s) => {
+      buf.push(1);
+      write_str(buf, s)?;
+    },
+    None => buf.push(0),
+  }
+
+  Ok(())
+}
+
+// reads the binary layout from the front of the slice
+// every read fails instead of panicking when there aren't enough bytes
+struct Reader<'a> {
+  bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+  fn take(&mut self, n: usize) -> Result<&'a [u8], SnapshotError> {
+    if self.bytes.len() < n {
+      return Err(SnapshotError::Malformed("unexpected end of snapshot"));
+    }
+
+    let (head, rest) = self.bytes.split_at(n);
+    self.bytes = rest;
+    Ok(head)
+  }
+
+  fn u8(&mut self) -> Result<u8, SnapshotError> {
+    Ok(self.take(1)?[0])
+  }
+
+  fn u32(&mut self) -> Result<u32, SnapshotError> {
+    let mut n = [0; 4];
+    n.copy_from_slice(self.take(4)?);
+    Ok(u32::from_le_bytes(n))
+  }
+
+  fn u64(&mut self) -> Result<u64, SnapshotError> {
+    let mut n = [0; 8];
+    n.copy_from_slice(self.take(8)?);
+    Ok(u64::from_le_bytes(n))
+  }
+
+  fn string(&mut self) -> Result<String, SnapshotError> {
+    let len = self.u32()? as usize;
+    let bytes = self.take(len)?;
+
+    String::from_utf8(bytes.to_vec())
+      .map_err(|_| SnapshotError::Malformed("string is not valid utf-8"))
+  }
+
+  fn opt_string(&mut self) -> Result<Option<String>, SnapshotError> {
+    match self.u8()? {
+      0 => Ok(None),
+      1 => Ok(Some(self.string()?)),
+      _ => Err(SnapshotError::Malformed("invalid option flag")),
+    }
+  }
+
+  // the nanoseconds have to be less than a second, and the time
+  // has to fit in a SystemTime, or adding them up would panic
+  fn timestamp(&mut self) -> Result<SystemTime, SnapshotError> {
+    let secs = self.u64()?;
+    let nanos = self.u32()?;
+
+    if nanos >= 1_000_000_000 {
+      return Err(SnapshotError::Malformed("invalid timestamp nanoseconds"));
+    }
+
+    UNIX_EPOCH
+      .checked_add(Duration::new(secs, nanos))
+      .ok_or(SnapshotError::Malformed("timestamp out of range"))
+  }
+
+  fn bool(&mut self) -> Result<bool, SnapshotError> {
+    match self.u8()? {
+      0 => Ok(false),
+      1 => Ok(true),
+      _ => Err(SnapshotError::Malformed("invalid bool")),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn pending_post() -> Post {
+    let mut post = Post::with_policy(ReviewPolicy { required_approvals: 2 });
+    post.add_text("I ate a salad for lunch today");
+    post.request_review();
+    post.approve_by("alice");
+    post
+  }
+
+  #[test]
+  fn json_round_trip_keeps_the_review_going() {
+    let json = pending_post().snapshot().to_json();
+
+    let snapshot = Snapshot::from_json(&json).unwrap();
+    let mut post = Post::from_snapshot(snapshot).unwrap();
+
+    assert_eq!("PendingReview", post.state_name());
+    assert_eq!(["alice"], post.approvals());
+    assert_eq!(2, post.history().len());
+
+    post.approve_by("bob");
+    assert_eq!("I ate a salad for lunch today", post.content());
+  }
+
+  #[test]
+  fn binary_round_trip() {
+    let snapshot = pending_post().snapshot();
+
+    let restored = Snapshot::from_bytes(&snapshot.to_bytes().unwrap()).unwrap();
+
+    assert_eq!(snapshot, restored);
+  }
+
+  #[test]
+  fn unknown_state_is_rejected() {
+    let mut snapshot = pending_post().snapshot();
+    snapshot.state = String::from("Archived");
+
+    match Post::from_snapshot(snapshot) {
+      Err(SnapshotError::UnknownState(name)) => assert_eq!("Archived", name),
+      _ => panic!("expected an unknown state error"),
+    }
+  }
+
+  #[test]
+  fn registered_states_can_be_restored() {
+    use crate::State;
+
+    struct Scheduled {
+      at: String,
+    }
+
+    impl State for Scheduled {
+      fn name(&self) -> &'static str {
+        "Scheduled"
+      }
+
+      fn request_review(self: Box<Self>) -> Box<dyn State> {
+        self
+      }
+
+      fn approve(self: Box<Self>, _reviewer: &str, _policy: &ReviewPolicy) -> Box<dyn State> {
+        self
+      }
+
+      fn reject(self: Box<Self>) -> Box<dyn State> {
+        self
+      }
+
+      fn payload(&self) -> Option<String> {
+        Some(self.at.clone())
+      }
+    }
+
+    let mut registry = Registry::new();
+    registry.register_state("Scheduled", |data| {
+      Box::new(Scheduled { at: data.payload.clone().unwrap_or_default() })
+    });
+    registry.register_action("schedule", "PendingReview", |_, at| {
+      Box::new(Scheduled { at: at.unwrap_or_default().to_string() })
+    }).unwrap();
+    let registry = Arc::new(registry);
+
+    let mut post = Post::with_registry(Arc::clone(&registry), ReviewPolicy::default());
+    post.request_review();
+    post.act("schedule", Some("2030-01-01"));
+
+    let bytes = post.snapshot().to_bytes().unwrap();
+    let snapshot = Snapshot::from_bytes(&bytes).unwrap();
+
+    assert!(Post::from_snapshot(snapshot.clone()).is_err());
+
+    let post = Post::from_snapshot_with(snapshot, registry).unwrap();
+    assert_eq!("Scheduled", post.state_name());
+    assert_eq!(Some(String::from("2030-01-01")), post.snapshot().payload);
+  }
+
+  #[test]
+  fn revisions_survive_a_round_trip() {
+    let mut post = Post::new();
+    post.add_text("I ate a salad for lunch today");
+    post.request_review();
+    post.approve();
+    post.replace_text("I ate a soup for lunch today");
+
+    let bytes = post.snapshot().to_bytes().unwrap();
+    let post = Post::from_snapshot(Snapshot::from_bytes(&bytes).unwrap()).unwrap();
+
+    assert_eq!(2, post.revision());
+    assert_eq!(Some(1), post.published_revision());
+    assert_eq!("I ate a salad for lunch today", post.content());
+    assert_eq!("I ate a soup for lunch today", post.text());
+  }
+
+  #[test]
+  fn comments_survive_a_round_trip() {
+    let mut post = pending_post();
+    post.add_comment("bob", 8..13, "which salad?");
+    post.reject_with("needs more detail");
+
+    let snapshot = post.snapshot();
+    let restored = Snapshot::from_bytes(&snapshot.to_bytes().unwrap()).unwrap();
+    assert_eq!(snapshot, restored);
+
+    let post = Post::from_snapshot(restored).unwrap();
+    assert_eq!(2, post.open_comments().len());
+    assert_eq!(Some(8..13), post.comments()[0].anchor);
+  }
+
+  #[test]
+  fn comments_are_checked() {
+    let mut post = Post::new();
+    post.add_text("I ate a café for lunch today");
+    post.add_comment("bob", 8..13, "a café?");
+    post.add_comment("bob", 0..1, "who?");
+    let snapshot = post.snapshot();
+
+    let broken: Vec<fn(&mut Snapshot)> = vec![
+      |s| s.comments[1].id = 0,
+      |s| s.comments.swap(0, 1),
+      |s| s.comments[0].revision = 9,
+      |s| s.comments[0].anchor = Some(8..100),
+      // in the middle of the é
+      |s| s.comments[0].anchor = Some(8..12),
+      |s| s.comments[0].anchor = Some(0..5),
+    ];
+
+    for f in broken {
+      let mut snapshot = snapshot.clone();
+      f(&mut snapshot);
+
+      match Post::from_snapshot(snapshot) {
+        Err(SnapshotError::Malformed(_)) => {},
+        _ => panic!("expected a malformed snapshot error"),
+      }
+    }
+
+    // outdated comments keep the anchor they had
+    let mut outdated = snapshot;
+    outdated.comments[0].anchor = Some(8..100);
+    outdated.comments[0].outdated = true;
+    assert!(Post::from_snapshot(outdated).is_ok());
+  }
+
+  #[test]
+  fn content_has_to_match_the_revisions() {
+    let mut snapshot = pending_post().snapshot();
+    snapshot.content = String::from("something else");
+
+    match Post::from_snapshot(snapshot) {
+      Err(SnapshotError::Malformed(_)) => {},
+      _ => panic!("expected a malformed snapshot error"),
+    }
+  }
+
+  #[test]
+  fn truncated_bytes_are_rejected() {
+    let bytes = pending_post().snapshot().to_bytes().unwrap();
+
+    match Snapshot::from_bytes(&bytes[..bytes.len() - 1]) {
+      Err(SnapshotError::Malformed(_)) => {},
+      _ => panic!("expected a malformed snapshot error"),
+    }
+  }
+
+  // a snapshot with a single transition at the given time, and the offset
+  // of the timestamp in its binary form
+  fn with_timestamp(at: SystemTime) -> (Vec<u8>, usize) {
+    let mut snapshot = Post::new().snapshot();
+    snapshot.history.push(Transition {
+      from: String::from("Draft"),
+      to: String::from("PendingReview"),
+      action: String::from("request_review"),
+      actor: None,
+      at,
+      no_op: false,
+    });
+
+    let bytes = snapshot.to_bytes().unwrap();
+    let since_epoch = at.duration_since(UNIX_EPOCH).unwrap();
+    let secs = since_epoch.as_secs().to_le_bytes();
+    let at = bytes.windows(8).position(|w| w == secs).unwrap();
+
+    (bytes, at)
+  }
+
+  #[test]
+  fn bad_timestamps_are_rejected() {
+    let (bytes, at) = with_timestamp(UNIX_EPOCH + Duration::new(0x0102_0304_0506, 7));
+    assert!(Snapshot::from_bytes(&bytes).is_ok());
+
+    let bad = [
+      // more nanoseconds than a second has
+      (1u64, 1_000_000_000u32),
+      (u64::MAX, u32::MAX),
+      // more seconds than a SystemTime can hold
+      (u64::MAX, 0),
+    ];
+
+    for (secs, nanos) in &bad {
+      let mut bytes = bytes.clone();
+      bytes[at..at + 8].copy_from_slice(&secs.to_le_bytes());
+      bytes[at + 8..at + 12].copy_from_slice(&nanos.to_le_bytes());
+
+      match Snapshot::from_bytes(&bytes) {
+        Err(SnapshotError::Malformed(_)) => {},
+        other => panic!("expected a malformed snapshot error, got {:?}", other),
+      }
+    }
+  }
+
+  #[test]
+  fn corrupted_bytes_never_panic() {
+    let (bytes, _) = with_timestamp(SystemTime::now());
+
+    for len in 0..bytes.len() {
+      assert!(Snapshot::from_bytes(&bytes[..len]).is_err());
+    }
+
+    for i in 0..bytes.len() {
+      for value in &[0, 1, 0x7f, 0x80, 0xff] {
+        let mut bytes = bytes.clone();
+        bytes[i] = *value;
+        // only checks that it returns at all
+        let _ = Snapshot::from_bytes(&bytes);
+      }
+    }
+  }
+
+  #[test]
+  fn numbers_too_large_for_the_layout_are_refused() {
+    let mut snapshot = pending_post().snapshot();
+    snapshot.required_approvals = u32::MAX as usize + 1;
+
+    match snapshot.to_bytes() {
+      Err(SnapshotError::TooLarge(what)) => assert_eq!("required approvals", what),
+      other => panic!("expected a too large error, got {:?}", other),
+    }
+  }
+
+  #[test]
+  fn other_versions_are_rejected() {
+    for version in &[0, SNAPSHOT_VERSION + 1] {
+      let mut bytes = pending_post().snapshot().to_bytes().unwrap();
+      bytes[4] = *version;
+
+      match Snapshot::from_bytes(&bytes) {
+        Err(SnapshotError::UnsupportedVersion(v)) => assert_eq!(*version, v),
+        _ => panic!("expected an unsupported version error"),
+      }
+    }
+
+    let mut snapshot = pending_post().snapshot();
+    snapshot.version = SNAPSHOT_VERSION + 1;
+
+    match Snapshot::from_json(&snapshot.to_json()) {
+      Err(SnapshotError::UnsupportedVersion(_)) => {},
+      _ => panic!("expected an unsupported version error"),
+    }
+  }
+}
@@ -0,0 +1,345 @@
+/*
+  ** A PostStore lets many threads work on the same posts, the way
+  ** the counter is shared in the concurrency chapter: Arc<T> to give
+  ** every thread ownership of the store and Mutex<T> to take turns on it.
+  **
+  ** Every post has its own Mutex, so threads working on different posts
+  ** only wait on each other while looking the post up.
+  **
+  ** Changes are checked with version numbers (optimistic concurrency).
+  ** Whoever wants to change a post says which version they looked at and
+  ** the change is refused with a Conflict when somebody else changed the
+  ** post in the meantime. This way two reviewers approving the same post
+  ** at once don't both get to act on it without seeing the other's approval.
+  **
+  ** A closure that panics in the middle of an update doesn't leave the post
+  ** half changed: the closure works on a copy of the post that only takes
+  ** its place once the closure returns, so the post and its version stay
+  ** the way they were and the panic carries on to the caller. A post in a
+  ** state that its registry can't build again can't be copied, so it's
+  ** changed in place, and if the closure panics it's marked as broken and
+  ** refused from then on instead of being used half changed.
+*/
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+
+use crate::Post;
+
+pub type PostId = u64;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum StoreError {
+  NotFound(PostId),
+  // the post is at version "actual" but the change was made looking at "expected"
+  Conflict { id: PostId, expected: u64, actual: u64 },
+  // an update panicked halfway and the post couldn't be put back
+  Broken(PostId),
+}
+
+impl fmt::Display for StoreError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      StoreError::NotFound(id) => write!(f, "post {} not found", id),
+      StoreError::Conflict { id, expected, actual } => write!(
+        f,
+        "post {} was changed: expected version {} but it is at {}",
+        id, expected, actual
+      ),
+      StoreError::Broken(id) => write!(f, "post {} was left half changed by an update that panicked", id),
+    }
+  }
+}
+
+impl Error for StoreError {}
+
+struct Entry {
+  post: Post,
+  version: u64,
+  broken: bool,
+}
+
+struct Posts {
+  next_id: PostId,
+  entries: HashMap<PostId, Arc<Mutex<Entry>>>,
+}
+
+// cloning a store gives another handle to the same posts
+#[derive(Clone)]
+pub struct PostStore {
+  posts: Arc<Mutex<Posts>>,
+}
+
+impl PostStore {
+  pub fn new() -> PostStore {
+    PostStore {
+      posts: Arc::new(Mutex::new(Posts {
+        next_id: 1,
+        entries: HashMap::new(),
+      })),
+    }
+  }
+
+  // new posts start at version 1
+  pub fn insert(&self, post: Post) -> PostId {
+    let mut posts = lock(&self.posts);
+
+    let id = posts.next_id;
+    posts.next_id += 1;
+    posts.entries.insert(id, Arc::new(Mutex::new(Entry { post, version: 1, broken: false })));
+
+    id
+  }
+
+  pub fn remove(&self, id: PostId) -> Result<(), StoreError> {
+    match lock(&self.posts).entries.remove(&id) {
+      Some(_) => Ok(()),
+      None => Err(StoreError::NotFound(id)),
+    }
+  }
+
+  pub fn version(&self, id: PostId) -> Result<u64, StoreError> {
+    self.read(id, |_| ()).map(|(_, version)| version)
+  }
+
+  // gives f a look at the post, together with the version it saw
+  pub fn read<F, R>(&self, id: PostId, f: F) -> Result<(R, u64), StoreError>
+    where F: FnOnce(&Post) -> R
+  {
+    let entry = self.entry(id)?;
+    let entry = lock(&entry);
+
+    if entry.broken {
+      return Err(StoreError::Broken(id));
+    }
+
+    Ok((f(&entry.post), entry.version))
+  }
+
+  // runs f on the post only if it is still at the expected version
+  // and returns what f returned along with the new version
+  pub fn update<F, R>(&self, id: PostId, expected: u64, f: F) -> Result<(R, u64), StoreError>
+    where F: FnOnce(&mut Post) -> R
+  {
+    let entry = self.entry(id)?;
+    let mut entry = lock(&entry);
+
+    if entry.broken {
+      return Err(StoreError::Broken(id));
+    }
+    if entry.version != expected {
+      return Err(StoreError::Conflict { id, expected, actual: entry.version });
+    }
+
+    // if f panics the copy is dropped and the post never changed
+    if let Ok(mut copy) = entry.post.duplicate() {
+      let result = f(&mut copy);
+      entry.post = copy;
+      entry.version += 1;
+      return Ok((result, entry.version));
+    }
+
+    match panic::catch_unwind(AssertUnwindSafe(|| f(&mut entry.post))) {
+      Ok(result) => {
+        entry.version += 1;
+        Ok((result, entry.version))
+      },
+      Err(panic) => {
+        entry.broken = true;
+        drop(entry);
+        panic::resume_unwind(panic)
+      },
+    }
+  }
+
+  // the store lock is only held while looking up the post,
+  // the post's own lock is taken by the caller after that
+  fn entry(&self, id: PostId) -> Result<Arc<Mutex<Entry>>, StoreError> {
+    match lock(&self.posts).entries.get(&id) {
+      Some(entry) => Ok(Arc::clone(entry)),
+      None => Err(StoreError::NotFound(id)),
+    }
+  }
+}
+
+// a panic while holding one of the locks can't leave anything half
+// done: reads don't change anything, updates work on a copy and a
+// post that was changed in place is marked as broken
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+  mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+impl Default for PostStore {
+  fn default() -> PostStore {
+    PostStore::new()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::sync::Barrier;
+  use std::thread;
+
+  use crate::{Registry, ReviewPolicy, State};
+
+  fn pending_post() -> Post {
+    let mut post = Post::new();
+    post.add_text("I ate a salad for lunch today");
+    post.request_review();
+    post
+  }
+
+  #[test]
+  fn update_bumps_the_version() {
+    let store = PostStore::new();
+    let id = store.insert(Post::new());
+
+    let (_, version) = store.update(id, 1, |post| post.add_text("hi")).unwrap();
+
+    assert_eq!(2, version);
+    assert_eq!(Ok(2), store.version(id));
+    assert_eq!(Ok(("hi".to_string(), 2)), store.read(id, |post| post.text().to_string()));
+  }
+
+  #[test]
+  fn stale_version_is_a_conflict() {
+    let store = PostStore::new();
+    let id = store.insert(Post::new());
+
+    store.update(id, 1, |post| post.add_text("hi")).unwrap();
+
+    assert_eq!(
+      Err(StoreError::Conflict { id, expected: 1, actual: 2 }),
+      store.update(id, 1, |post| post.add_text("there"))
+    );
+  }
+
+  #[test]
+  fn missing_posts() {
+    let store = PostStore::new();
+    let id = store.insert(Post::new());
+
+    assert_eq!(Ok(()), store.remove(id));
+    assert_eq!(Err(StoreError::NotFound(id)), store.version(id));
+    assert_eq!(Err(StoreError::NotFound(id)), store.remove(id));
+  }
+
+  #[test]
+  fn a_panicking_update_is_rolled_back() {
+    let store = PostStore::new();
+    let id = store.insert(pending_post());
+
+    let result = panic::catch_unwind(|| {
+      store.update(id, 1, |post| {
+        post.reject();
+        post.add_text(", and a soup");
+        panic!("the update failed halfway");
+      })
+    });
+    assert!(result.is_err());
+
+    let (state, version) = store.read(id, |post| (post.state_name(), post.text().to_string())).unwrap();
+    assert_eq!(("PendingReview", String::from("I ate a salad for lunch today")), state);
+    assert_eq!(1, version);
+
+    // the post is still usable
+    assert_eq!(Ok(((), 2)), store.update(id, 1, |post| post.approve()));
+    assert_eq!(Ok(("Published", 2)), store.read(id, |post| post.state_name()));
+  }
+
+  // a state the post's registry doesn't know, so the post can't be copied
+  struct Lost {}
+
+  impl State for Lost {
+    fn name(&self) -> &'static str {
+      "Lost"
+    }
+
+    fn request_review(self: Box<Self>) -> Box<dyn State> {
+      self
+    }
+
+    fn approve(self: Box<Self>, _reviewer: &str, _policy: &ReviewPolicy) -> Box<dyn State> {
+      self
+    }
+
+    fn reject(self: Box<Self>) -> Box<dyn State> {
+      self
+    }
+  }
+
+  #[test]
+  fn a_post_that_cant_be_put_back_is_broken() {
+    let mut registry = Registry::new();
+    registry.register_action("lose", "Draft", |_, _| Box::new(Lost {})).unwrap();
+
+    let mut post = Post::with_registry(Arc::new(registry), ReviewPolicy::default());
+    post.act("lose", None);
+
+    let store = PostStore::new();
+    let id = store.insert(post);
+
+    // without a panic the post is changed in place
+    assert_eq!(Ok(((), 2)), store.update(id, 1, |post| post.add_text("hi")));
+
+    let result = panic::catch_unwind(|| {
+      store.update(id, 2, |post| {
+        post.add_text(" there");
+        panic!("the update failed halfway");
+      })
+    });
+    assert!(result.is_err());
+
+    assert_eq!(Err(StoreError::Broken(id)), store.version(id));
+    assert_eq!(Err(StoreError::Broken(id)), store.update(id, 2, |post| post.add_text("!")));
+    assert_eq!(Ok(()), store.remove(id));
+  }
+
+  #[test]
+  fn a_panicking_read_does_not_lock_the_post_out() {
+    let store = PostStore::new();
+    let id = store.insert(Post::new());
+
+    let result = panic::catch_unwind(|| store.read(id, |_| panic!("oops")));
+    assert!(result.is_err());
+
+    assert_eq!(Ok(1), store.version(id));
+  }
+
+  #[test]
+  fn concurrent_approvals_publish_once() {
+    let store = PostStore::new();
+    let id = store.insert(pending_post());
+
+    // both reviewers look at the post before either of them approves it
+    let barrier = Arc::new(Barrier::new(2));
+    let mut handles = vec![];
+
+    for reviewer in &["alice", "bob"] {
+      let store = store.clone();
+      let barrier = Arc::clone(&barrier);
+
+      handles.push(thread::spawn(move || {
+        let version = store.version(id).unwrap();
+        barrier.wait();
+
+        store.update(id, version, |post| post.approve_by(reviewer))
+      }));
+    }
+
+    let results: Vec<_> = handles.into_iter().map(|h| h.join().unwrap()).collect();
+
+    assert_eq!(1, results.iter().filter(|r| r.is_ok()).count());
+    assert_eq!(1, results.iter().filter(|r| matches!(r, Err(StoreError::Conflict { .. }))).count());
+
+    let (approvals, _) = store.read(id, |post| {
+      assert_eq!("Published", post.state_name());
+      post.history().iter().filter(|t| t.action == "approve").count()
+    }).unwrap();
+    assert_eq!(1, approvals);
+  }
+}
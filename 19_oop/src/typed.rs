@@ -0,0 +1,156 @@
+//! The same blog post workflow as `oop::Post`, but with each state
+//! encoded as its own type instead of a `Box<dyn State>`.
+//!
+//! Every transition consumes the post and returns the next state type,
+//! so an invalid transition is a compile error instead of a silent no-op.
+//! The content of a post can only be read once it is a `PublishedPost`:
+//!
+//! ```
+//! use oop::typed::DraftPost;
+//!
+//! let mut post = DraftPost::new();
+//! post.add_text("I ate a salad for lunch today");
+//!
+//! let post = post.request_review().approve();
+//! assert_eq!("I ate a salad for lunch today", post.content());
+//! ```
+//!
+//! A draft has no `content` method:
+//!
+//! ```compile_fail
+//! use oop::typed::DraftPost;
+//!
+//! let mut post = DraftPost::new();
+//! post.add_text("I ate a salad for lunch today");
+//! post.content();
+//! ```
+//!
+//! Neither does a post that is waiting for a review:
+//!
+//! ```compile_fail
+//! use oop::typed::DraftPost;
+//!
+//! let post = DraftPost::new().request_review();
+//! post.content();
+//! ```
+//!
+//! A draft can't be approved without going through a review first:
+//!
+//! ```compile_fail
+//! use oop::typed::DraftPost;
+//!
+//! let post = DraftPost::new().approve();
+//! ```
+//!
+//! And a rejected post goes back to being a draft, so it has to be
+//! reviewed again before it can be published:
+//!
+//! ```compile_fail
+//! use oop::typed::DraftPost;
+//!
+//! let post = DraftPost::new().request_review().reject();
+//! post.content();
+//! ```
+//!
+//! Since every transition takes ownership, the old state can't be used
+//! after it was moved into the new one:
+//!
+//! ```compile_fail
+//! use oop::typed::DraftPost;
+//!
+//! let post = DraftPost::new();
+//! let pending = post.request_review();
+//! post.request_review();
+//! ```
+
+pub struct DraftPost {
+  content: String,
+}
+
+impl DraftPost {
+  // every post still starts as an empty draft
+  // there's just no Post wrapper deciding what state it is in
+  pub fn new() -> DraftPost {
+    DraftPost {
+      content: String::new(),
+    }
+  }
+
+  pub fn add_text(&mut self, text: &str) {
+    self.content.push_str(text);
+  }
+
+  // this takes ownership of self, so the draft
+  // can't be used anymore once the review was requested
+  pub fn request_review(self) -> PendingReviewPost {
+    PendingReviewPost {
+      content: self.content,
+    }
+  }
+}
+
+impl Default for DraftPost {
+  fn default() -> DraftPost {
+    DraftPost::new()
+  }
+}
+
+pub struct PendingReviewPost {
+  content: String,
+}
+
+impl PendingReviewPost {
+  pub fn approve(self) -> PublishedPost {
+    PublishedPost {
+      content: self.content,
+    }
+  }
+
+  // rejecting keeps the text that was written so far
+  // and hands back a draft that can be edited again
+  pub fn reject(self) -> DraftPost {
+    DraftPost {
+      content: self.content,
+    }
+  }
+}
+
+pub struct PublishedPost {
+  content: String,
+}
+
+impl PublishedPost {
+  // this is the only type with a content method
+  // so trying to read an unpublished post won't compile
+  pub fn content(&self) -> &str {
+    &self.content
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn approved_post_shows_content() {
+    let mut post = DraftPost::new();
+    post.add_text("I ate a salad for lunch today");
+
+    let post = post.request_review().approve();
+
+    assert_eq!("I ate a salad for lunch today", post.content());
+  }
+
+  #[test]
+  fn rejected_post_keeps_its_text() {
+    let mut post = DraftPost::new();
+    post.add_text("I ate a salad");
+
+    let mut post = post.request_review().reject();
+    post.add_text(" for lunch today");
+
+    let post = post.request_review().approve();
+
+    assert_eq!("I ate a salad for lunch today", post.content());
+  }
+}